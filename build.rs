@@ -0,0 +1,28 @@
+use std::{env, fs, path::PathBuf};
+
+// 未经tools/ksymtab统计过时.ksymtab预留的大小
+const DEFAULT_KSYMTAB_SIZE: usize = 256 * 1024;
+
+// 根据tools/ksymtab上次运行时写入的ksymtab-size生成.ksymtab的大小
+fn main() {
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    // OUT_DIR为<构建目录>/build/<包>/out, ksymtab-size位于构建目录下
+    let size_file = out_dir.ancestors().nth(3).unwrap().join("ksymtab-size");
+
+    // 文件不存在时cargo每次都会重新运行build.rs, 因此先创建一个空文件
+    if !size_file.exists() {
+        fs::write(&size_file, "").unwrap();
+    }
+    println!("cargo:rerun-if-changed={}", size_file.display());
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let size = fs::read_to_string(&size_file)
+        .ok()
+        .and_then(|size| size.trim().parse().ok())
+        .unwrap_or(DEFAULT_KSYMTAB_SIZE);
+    fs::write(
+        out_dir.join("ksymtab_size.rs"),
+        format!("const TABLE_SIZE: usize = {};\n", size),
+    )
+    .unwrap();
+}
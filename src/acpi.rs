@@ -0,0 +1,194 @@
+use alloc::vec::Vec;
+use core::slice;
+
+use x86_64::VirtAddr;
+
+use crate::memory;
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+const MADT_SIGNATURE: &[u8; 4] = b"APIC";
+const SDT_HEADER_SIZE: usize = 36;
+
+// MADT中各类条目的类型
+const MADT_LOCAL_APIC: u8 = 0;
+const MADT_IO_APIC: u8 = 1;
+const MADT_INTERRUPT_OVERRIDE: u8 = 2;
+const MADT_LOCAL_APIC_ADDRESS: u8 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcpiError {
+    NotInitialized, // 物理内存映射尚未初始化
+    RsdpNotFound,
+    TableNotFound([u8; 4]),
+    InvalidChecksum([u8; 4]),
+}
+
+// 一个处理器的本地APIC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalApicEntry {
+    pub processor_id: u8,
+    pub apic_id: u8,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoApicEntry {
+    pub id: u8,
+    pub address: u64,  // 寄存器的物理地址
+    pub gsi_base: u32, // 第一条中断线对应的全局系统中断号
+}
+
+// ISA中断线到全局系统中断的重定向, 以及该中断的极性和触发方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterruptOverride {
+    pub source: u8,
+    pub gsi: u32,
+    pub flags: u16,
+}
+
+impl InterruptOverride {
+    // 极性为0b11时低电平有效, 0b00表示遵循总线规范, ISA为高电平有效
+    pub fn active_low(&self) -> bool {
+        self.flags & 0b11 == 0b11
+    }
+
+    // 触发方式为0b11时电平触发, 0b00表示遵循总线规范, ISA为边沿触发
+    pub fn level_triggered(&self) -> bool {
+        (self.flags >> 2) & 0b11 == 0b11
+    }
+}
+
+// 多APIC描述表中与中断控制器相关的内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Madt {
+    pub local_apic_address: u64,
+    pub legacy_pics: bool, // 是否同时存在两片8259
+    pub local_apics: Vec<LocalApicEntry>,
+    pub io_apics: Vec<IoApicEntry>,
+    pub overrides: Vec<InterruptOverride>,
+}
+
+impl Madt {
+    // ISA中断线irq的重定向, 没有时irq直接对应同号的全局系统中断
+    pub fn override_for(&self, irq: u8) -> Option<&InterruptOverride> {
+        self.overrides.iter().find(|entry| entry.source == irq)
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) == 0
+}
+
+// 通过引导程序建立的物理内存映射访问物理地址addr开始的len字节
+fn physical_bytes(offset: VirtAddr, addr: u64, len: usize) -> &'static [u8] {
+    unsafe { slice::from_raw_parts((offset + addr).as_ptr(), len) }
+}
+
+// 在EBDA的前1KiB以及BIOS只读区域0xe0000-0xfffff中按16字节对齐查找RSDP, 返回其物理地址
+fn find_rsdp(offset: VirtAddr) -> Option<u64> {
+    let ebda = u64::from(u16_at(physical_bytes(offset, 0x40e, 2), 0)) << 4;
+    let areas = [(ebda, ebda + 1024), (0xe0000, 0x100000)];
+
+    areas
+        .iter()
+        .filter(|&&(start, _)| start != 0)
+        .flat_map(|&(start, end)| (start..end).step_by(16))
+        .find(|&addr| {
+            let rsdp = physical_bytes(offset, addr, 20);
+            rsdp.starts_with(RSDP_SIGNATURE) && checksum_ok(rsdp)
+        })
+}
+
+// 物理地址addr处的一个系统描述表, 包括表头
+fn sdt(offset: VirtAddr, addr: u64) -> &'static [u8] {
+    let header = physical_bytes(offset, addr, SDT_HEADER_SIZE);
+    physical_bytes(offset, addr, u32_at(header, 4) as usize)
+}
+
+// 按签名查找ACPI表, 返回的数据包括表头
+pub fn find_table(signature: &[u8; 4]) -> Result<&'static [u8], AcpiError> {
+    let offset = memory::physical_memory_offset().ok_or(AcpiError::NotInitialized)?;
+    let rsdp = physical_bytes(
+        offset,
+        find_rsdp(offset).ok_or(AcpiError::RsdpNotFound)?,
+        36,
+    );
+
+    // ACPI 2.0起优先使用64位地址的XSDT
+    let (root, entry_size) = match (rsdp[15], u64_at(rsdp, 24)) {
+        (0, _) | (_, 0) => (u64::from(u32_at(rsdp, 16)), 4),
+        (_, xsdt) => (xsdt, 8),
+    };
+
+    let root = sdt(offset, root);
+    let table = root[SDT_HEADER_SIZE..]
+        .chunks_exact(entry_size)
+        .map(|entry| match entry_size {
+            4 => u64::from(u32_at(entry, 0)),
+            _ => u64_at(entry, 0),
+        })
+        .map(|addr| sdt(offset, addr))
+        .find(|table| &table[..4] == signature)
+        .ok_or(AcpiError::TableNotFound(*signature))?;
+
+    match checksum_ok(table) {
+        true => Ok(table),
+        false => Err(AcpiError::InvalidChecksum(*signature)),
+    }
+}
+
+// 读取并解析MADT
+pub fn madt() -> Result<Madt, AcpiError> {
+    let table = find_table(MADT_SIGNATURE)?;
+    let mut madt = Madt {
+        local_apic_address: u64::from(u32_at(table, SDT_HEADER_SIZE)),
+        legacy_pics: u32_at(table, SDT_HEADER_SIZE + 4) & 1 != 0,
+        local_apics: Vec::new(),
+        io_apics: Vec::new(),
+        overrides: Vec::new(),
+    };
+
+    // 每个条目以类型和长度开头
+    let mut offset = SDT_HEADER_SIZE + 8;
+    while offset + 2 <= table.len() {
+        let (kind, len) = (table[offset], table[offset + 1] as usize);
+        if len < 2 || offset + len > table.len() {
+            break;
+        }
+        let entry = &table[offset..offset + len];
+        match kind {
+            MADT_LOCAL_APIC => madt.local_apics.push(LocalApicEntry {
+                processor_id: entry[2],
+                apic_id: entry[3],
+                enabled: u32_at(entry, 4) & 1 != 0,
+            }),
+            MADT_IO_APIC => madt.io_apics.push(IoApicEntry {
+                id: entry[2],
+                address: u64::from(u32_at(entry, 4)),
+                gsi_base: u32_at(entry, 8),
+            }),
+            MADT_INTERRUPT_OVERRIDE => madt.overrides.push(InterruptOverride {
+                source: entry[3],
+                gsi: u32_at(entry, 4),
+                flags: u16_at(entry, 8),
+            }),
+            MADT_LOCAL_APIC_ADDRESS => madt.local_apic_address = u64_at(entry, 4),
+            _ => {}
+        }
+        offset += len;
+    }
+
+    Ok(madt)
+}
@@ -1,28 +1,90 @@
-use core::{alloc::GlobalAlloc, ptr::null_mut};
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ptr::null_mut,
+};
 
+use linked_list_allocator::align_up;
 use x86_64::{
     structures::paging::{
-        mapper::MapToError, FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB,
+        mapper::MapToError, FrameAllocator, Mapper, Page, PageSize, PageTableFlags, Size2MiB,
+        Size4KiB,
     },
     VirtAddr,
 };
 
+use crate::{memory, report};
+
+use fixed_size_block::BLOCK_SIZES;
+
 pub mod bump;
 pub mod linked_list;
 pub mod fixed_size_block;
+pub mod slab;
+
+// 全局分配器的实现, 由cargo特性alloc-bump, alloc-linked-list, alloc-fixed-block选择
+#[cfg(feature = "alloc-bump")]
+pub type HeapAllocator = bump::BumpAllocator;
+#[cfg(all(feature = "alloc-linked-list", not(feature = "alloc-bump")))]
+pub type HeapAllocator = linked_list::LinkedListAllocator;
+#[cfg(not(any(feature = "alloc-bump", feature = "alloc-linked-list")))]
+pub type HeapAllocator = fixed_size_block::FixedSizeBlockAllocator;
 
 pub const HEAP_START: usize = 0x_4444_4444_0000;
 pub const HEAP_SIZE: usize = 100 * 1024; // 100KiB
 
+// 堆允许扩展到的最大大小
+pub const HEAP_MAX_SIZE: usize = 16 * 1024 * 1024; // 16MiB
+
+// 最大堆大小之后的一页不会被映射, 作为保护页
+const HEAP_GUARD_SIZE: usize = 4096;
+
+// 检查addr是否位于堆末尾的保护页中
+// 堆内尚未扩展到的部分不算越界, 由扩展堆时的分配失败处理
+pub fn heap_guard_hit(addr: VirtAddr) -> bool {
+    let addr = addr.as_u64() as usize;
+    let guard_start = HEAP_START + HEAP_MAX_SIZE;
+    let guard_end = guard_start + HEAP_GUARD_SIZE;
+
+    guard_start <= addr && addr < guard_end
+}
+
+// 每次扩展堆时至少映射的大小
+const HEAP_GROW_STEP: usize = 64 * 1024; // 64KiB
+
+// 堆内存的页表标志
+const HEAP_FLAGS: PageTableFlags = PageTableFlags::PRESENT
+    .union(PageTableFlags::WRITABLE)
+    .union(PageTableFlags::NO_EXECUTE);
+
+// 获取memory::KERNEL_MEMORY前堆中至少保留的空闲空间, 持有该锁期间的堆分配只能使用这部分空间
+pub const HEAP_RESERVE: usize = HEAP_GROW_STEP;
+
 // 初始化堆
 pub fn init_heap(
     mapper: &mut impl Mapper<Size4KiB>,
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
+    map_heap(HEAP_START, HEAP_SIZE, mapper, frame_allocator)?;
+
+    // 初始化分配器
+    unsafe {
+        crate::ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE);
+    }
+
+    Ok(())
+}
+
+// 为[start, start + size)范围内的堆内存映射物理帧
+fn map_heap(
+    start: usize,
+    size: usize,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
 ) -> Result<(), MapToError<Size4KiB>> {
     let page_range = {
         // 计算堆的起始和结束地址
-        let heap_start = VirtAddr::new(HEAP_START as u64);
-        let heap_end = heap_start + HEAP_SIZE - 1u64;
+        let heap_start = VirtAddr::new(start as u64);
+        let heap_end = heap_start + size - 1u64;
 
         // 获取堆的起始和结束页
         let heap_start_page: Page<Size4KiB> = Page::containing_address(heap_start);
@@ -36,17 +98,172 @@ pub fn init_heap(
         let frame = frame_allocator
             .allocate_frame()
             .ok_or(MapToError::FrameAllocationFailed)?;
-        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
 
-        unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
+        unsafe { mapper.map_to(page, frame, HEAP_FLAGS, frame_allocator)?.flush() };
     }
 
-    // 初始化分配器
-    unsafe {
-        crate::ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE);
+    Ok(())
+}
+
+// 在当前堆的末尾映射至少min_size字节的内存, 返回实际扩展的大小
+// 扩展需要通过memory::KERNEL_MEMORY进行映射, 若其未初始化或正被占用则扩展失败
+// 按2MiB对齐且足够大的部分优先映射为2MiB大页, 得不到连续的物理帧时退回到4KiB页
+fn grow_heap(heap_end: usize, min_size: usize) -> Option<usize> {
+    let size = align_up(min_size.max(HEAP_GROW_STEP), Size4KiB::SIZE as usize);
+    if heap_end + size > HEAP_START + HEAP_MAX_SIZE {
+        return None;
     }
 
-    Ok(())
+    // 持有KERNEL_MEMORY时只能使用memory::lock_kernel_memory预留的HEAP_RESERVE,
+    // 预留的空间可能已碎片化, 放不下的分配在这里失败并交给调用者处理
+    let mut kernel_memory = memory::KERNEL_MEMORY.try_lock()?;
+    let kernel_memory = kernel_memory.as_mut()?;
+    let huge_size = Size2MiB::SIZE as usize;
+    let mut offset = 0;
+    while offset < size {
+        let start = heap_end + offset;
+        let virt = VirtAddr::new(start as u64);
+        let huge_frame = if virt.is_aligned(Size2MiB::SIZE) && size - offset >= huge_size {
+            kernel_memory.frame_allocator.allocate_huge_frame()
+        } else {
+            None
+        };
+
+        offset += match huge_frame {
+            Some(frame) => unsafe {
+                memory::map_sized_page::<Size2MiB>(
+                    virt,
+                    frame.start_address(),
+                    HEAP_FLAGS,
+                    &mut kernel_memory.mapper,
+                    &mut kernel_memory.frame_allocator,
+                )
+                .ok()? as usize
+            },
+            // 用4KiB页映射到下一个2MiB边界为止
+            None => {
+                let len = (align_up(start + 1, huge_size) - start).min(size - offset);
+                map_heap(
+                    start,
+                    len,
+                    &mut kernel_memory.mapper,
+                    &mut kernel_memory.frame_allocator,
+                )
+                .ok()?;
+                len
+            }
+        };
+    }
+
+    Some(size)
+}
+
+// 堆的使用情况统计
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    pub heap_size: usize,                            // 堆的总大小
+    pub bytes_in_use: usize,                         // 已分配给调用者的字节数
+    pub peak_bytes_in_use: usize,                    // 已分配字节数的峰值
+    pub allocations: usize,                          // 尚未释放的分配数量
+    pub free_regions: usize,                         // 空闲区域的数量, 仅LinkedListAllocator
+    pub largest_free_region: usize,                  // 最大空闲区域的大小, 仅LinkedListAllocator
+    pub fallback_used: usize,                        // 后备堆已使用的字节数, 仅FixedSizeBlockAllocator
+    pub free_blocks: [usize; BLOCK_SIZES.len()],     // 各大小的空闲块数量, 仅FixedSizeBlockAllocator
+}
+
+// 返回全局分配器的使用情况
+pub fn heap_stats() -> HeapStats {
+    crate::ALLOCATOR.stats()
+}
+
+// 将全局分配器缓存的空闲块归还给后备堆, 返回归还的字节数
+// 只有FixedSizeBlockAllocator会缓存空闲块, 其他分配器总是返回0
+pub fn shrink_heap() -> usize {
+    #[cfg(not(any(feature = "alloc-bump", feature = "alloc-linked-list")))]
+    return crate::ALLOCATOR.shrink();
+
+    #[cfg(any(feature = "alloc-bump", feature = "alloc-linked-list"))]
+    return 0;
+}
+
+// 保证堆中至少有min_free字节的空闲空间, 不足时扩展堆, 返回是否满足
+// 只有FixedSizeBlockAllocator会扩展堆, 其他分配器总是返回true
+pub fn reserve_heap(min_free: usize) -> bool {
+    #[cfg(not(any(feature = "alloc-bump", feature = "alloc-linked-list")))]
+    return crate::ALLOCATOR.reserve(min_free);
+
+    #[cfg(any(feature = "alloc-bump", feature = "alloc-linked-list"))]
+    {
+        let _ = min_free;
+        true
+    }
+}
+
+// 分配失败时输出失败的Layout与堆的使用情况
+// 分配器在返回null前已释放锁, 因此这里可以再次查询统计信息
+#[alloc_error_handler]
+fn alloc_error_handler(layout: Layout) -> ! {
+    let stats = heap_stats();
+
+    report!("ALLOCATION ERROR: {:?}", layout);
+    report!(
+        "heap size: {}, in use: {} bytes ({} allocations), peak: {} bytes",
+        stats.heap_size,
+        stats.bytes_in_use,
+        stats.allocations,
+        stats.peak_bytes_in_use
+    );
+    report!(
+        "free regions: {}, largest: {} bytes",
+        stats.free_regions,
+        stats.largest_free_region
+    );
+    report!("fallback heap used: {} bytes", stats.fallback_used);
+    for (block_size, count) in BLOCK_SIZES.iter().zip(stats.free_blocks.iter()) {
+        report!("  free {} byte blocks: {}", block_size, count);
+    }
+
+    panic!("allocation error: {:?}", layout)
+}
+
+// 分配器内部记录的分配数量与已用字节数
+#[derive(Debug, Clone, Copy)]
+struct Usage {
+    bytes: usize,
+    peak: usize,
+    allocations: usize,
+}
+
+impl Usage {
+    const fn new() -> Self {
+        Usage {
+            bytes: 0,
+            peak: 0,
+            allocations: 0,
+        }
+    }
+
+    fn record_alloc(&mut self, size: usize) {
+        self.bytes += size;
+        self.peak = self.peak.max(self.bytes);
+        self.allocations += 1;
+    }
+
+    fn record_dealloc(&mut self, size: usize) {
+        self.bytes -= size;
+        self.allocations -= 1;
+    }
+
+    // 以当前记录填充统计信息, 其余字段由各分配器自行补充
+    fn stats(&self, heap_size: usize) -> HeapStats {
+        HeapStats {
+            heap_size,
+            bytes_in_use: self.bytes,
+            peak_bytes_in_use: self.peak,
+            allocations: self.allocations,
+            ..HeapStats::default()
+        }
+    }
 }
 
 pub struct Dummy;
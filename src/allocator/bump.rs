@@ -1,15 +1,19 @@
-use core::{alloc::GlobalAlloc, ptr};
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    marker::PhantomData,
+    ptr::{self, NonNull},
+};
 
 use linked_list_allocator::align_up;
 
-use super::Locked;
+use super::{HeapStats, Locked, Usage};
 
 // bump分配器
 pub struct BumpAllocator {
     heap_start: usize,
     heap_end: usize,
     next: usize,
-    allocations: usize,
+    usage: Usage,
 }
 
 impl BumpAllocator {
@@ -19,7 +23,7 @@ impl BumpAllocator {
             heap_start: 0,
             heap_end: 0,
             next: 0,
-            allocations: 0,
+            usage: Usage::new(),
         }
     }
 
@@ -31,32 +35,87 @@ impl BumpAllocator {
     }
 }
 
+impl Locked<BumpAllocator> {
+    pub fn stats(&self) -> HeapStats {
+        let allocator = self.lock();
+        allocator.usage.stats(allocator.heap_end - allocator.heap_start)
+    }
+}
+
+// 从next开始按layout分配, 返回分配的起始和结束地址
+fn bump(next: usize, end: usize, layout: Layout) -> Option<(usize, usize)> {
+    let alloc_start = align_up(next, layout.align());
+    let alloc_end = alloc_start.checked_add(layout.size())?;
+
+    // 检测分配是否超出堆区大小
+    if alloc_end > end {
+        None
+    } else {
+        Some((alloc_start, alloc_end))
+    }
+}
+
 unsafe impl GlobalAlloc for Locked<BumpAllocator> {
     unsafe fn alloc(&self, layout: core::alloc::Layout) -> *mut u8 {
         let mut allocator = self.lock();
 
-        let alloc_start = align_up(allocator.next, layout.size());
-        let alloc_end = match alloc_start.checked_add(layout.size()) {
-            Some(end) => end,
-            None => return ptr::null_mut(),
-        };
-
-        // 检测分配是否超出堆区大小
-        if alloc_end > allocator.heap_end {
-            ptr::null_mut()
-        } else {
-            allocator.next = alloc_end;
-            allocator.allocations += 1;
-            alloc_start as *mut u8
+        match bump(allocator.next, allocator.heap_end, layout) {
+            Some((alloc_start, alloc_end)) => {
+                allocator.next = alloc_end;
+                allocator.usage.record_alloc(layout.size());
+                alloc_start as *mut u8
+            }
+            None => ptr::null_mut(),
         }
     }
 
-    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: core::alloc::Layout) {
+    unsafe fn dealloc(&self, _ptr: *mut u8, layout: core::alloc::Layout) {
         let mut allocator = self.lock();
 
-        allocator.allocations -= 1;
-        if allocator.allocations == 0 {
+        allocator.usage.record_dealloc(layout.size());
+        if allocator.usage.allocations == 0 {
             allocator.next = allocator.heap_start;
         }
     }
 }
+
+// 基于一段给定内存的bump分配区, 用于临时的scratch内存
+// 分配的内存不能单独释放, 只能通过reset整体回收
+pub struct Arena<'a> {
+    start: usize,
+    end: usize,
+    next: usize,
+    _region: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> Arena<'a> {
+    pub fn new(region: &'a mut [u8]) -> Self {
+        let start = region.as_mut_ptr() as usize;
+        Arena {
+            start,
+            end: start + region.len(),
+            next: start,
+            _region: PhantomData,
+        }
+    }
+
+    // 分配一块内存, 空间不足时返回None
+    pub fn alloc(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        let (alloc_start, alloc_end) = bump(self.next, self.end, layout)?;
+        self.next = alloc_end;
+        NonNull::new(alloc_start as *mut u8)
+    }
+
+    // 回收所有已分配的内存, 之前返回的指针都将失效
+    pub fn reset(&mut self) {
+        self.next = self.start;
+    }
+
+    pub fn used(&self) -> usize {
+        self.next - self.start
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.end - self.next
+    }
+}
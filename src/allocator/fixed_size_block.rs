@@ -3,7 +3,7 @@ use core::{
     ptr::{self, NonNull}, mem,
 };
 
-use super::Locked;
+use super::{HeapStats, Locked, Usage};
 
 // 固定大小的块分配器
 struct ListNode {
@@ -11,11 +11,12 @@ struct ListNode {
 }
 
 // 块的大小
-const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+pub const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
 
 pub struct FixedSizeBlockAllocator {
     list_heads: [Option<&'static mut ListNode>; BLOCK_SIZES.len()], // 初始化一组固定大小的块
     fallback_allocator: linked_list_allocator::Heap, // 将未初始化的堆交给LinkedList分配器管理
+    usage: Usage,
 }
 
 impl FixedSizeBlockAllocator {
@@ -24,6 +25,7 @@ impl FixedSizeBlockAllocator {
         FixedSizeBlockAllocator {
             list_heads: [EMPTY; BLOCK_SIZES.len()],
             fallback_allocator: linked_list_allocator::Heap::empty(),
+            usage: Usage::new(),
         }
     }
 
@@ -33,10 +35,46 @@ impl FixedSizeBlockAllocator {
 
     // 回收空闲空间
     fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
-        match self.fallback_allocator.allocate_first_fit(layout) {
-            Ok(ptr) => ptr.as_ptr(),
-            Err(_) => ptr::null_mut(),
+        if let Ok(ptr) = self.fallback_allocator.allocate_first_fit(layout) {
+            return ptr.as_ptr();
         }
+
+        // 将缓存的空闲块归还给后备堆合并后重试
+        if self.reclaim_blocks() > 0 {
+            if let Ok(ptr) = self.fallback_allocator.allocate_first_fit(layout) {
+                return ptr.as_ptr();
+            }
+        }
+
+        // 堆空间不足时在堆末尾映射更多内存后重试
+        let heap_end = self.fallback_allocator.top();
+        match super::grow_heap(heap_end, layout.size() + layout.align()) {
+            Some(size) => {
+                unsafe { self.fallback_allocator.extend(size) };
+                match self.fallback_allocator.allocate_first_fit(layout) {
+                    Ok(ptr) => ptr.as_ptr(),
+                    Err(_) => ptr::null_mut(),
+                }
+            }
+            None => ptr::null_mut(),
+        }
+    }
+
+    // 将所有空闲链表中的块归还给后备堆, 返回归还的字节数
+    fn reclaim_blocks(&mut self) -> usize {
+        let mut reclaimed = 0;
+        for (index, &block_size) in BLOCK_SIZES.iter().enumerate() {
+            let layout = Layout::from_size_align(block_size, block_size).unwrap();
+            while let Some(node) = self.list_heads[index].take() {
+                self.list_heads[index] = node.next.take();
+
+                let ptr = NonNull::from(node).cast::<u8>();
+                unsafe { self.fallback_allocator.deallocate(ptr, layout) };
+                reclaimed += block_size;
+            }
+        }
+
+        reclaimed
     }
 }
 
@@ -46,11 +84,52 @@ fn list_index(layout: &Layout) -> Option<usize> {
     BLOCK_SIZES.iter().position(|&s| s >= required_block_size)
 }
 
+impl Locked<FixedSizeBlockAllocator> {
+    pub fn stats(&self) -> HeapStats {
+        let allocator = self.lock();
+        let mut stats = allocator.usage.stats(allocator.fallback_allocator.size());
+        stats.fallback_used = allocator.fallback_allocator.used();
+
+        for (index, count) in stats.free_blocks.iter_mut().enumerate() {
+            let mut current = &allocator.list_heads[index];
+            while let Some(node) = current {
+                *count += 1;
+                current = &node.next;
+            }
+        }
+
+        stats
+    }
+
+    // 主动将缓存的空闲块归还给后备堆, 返回归还的字节数
+    pub fn shrink(&self) -> usize {
+        self.lock().reclaim_blocks()
+    }
+
+    // 后备堆的空闲空间少于min_free时扩展堆, 返回扩展后是否满足
+    pub fn reserve(&self, min_free: usize) -> bool {
+        let mut allocator = self.lock();
+        let free = allocator.fallback_allocator.free();
+        if free >= min_free {
+            return true;
+        }
+
+        let heap_end = allocator.fallback_allocator.top();
+        match super::grow_heap(heap_end, min_free - free) {
+            Some(size) => {
+                unsafe { allocator.fallback_allocator.extend(size) };
+                true
+            }
+            None => false,
+        }
+    }
+}
+
 unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         let mut allocator = self.lock();
         // 匹配大小
-        match list_index(&layout) {
+        let ptr = match list_index(&layout) {
             Some(index) => match allocator.list_heads[index].take() {
                 Some(node) => {
                     allocator.list_heads[index] = node.next.take();
@@ -64,11 +143,17 @@ unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
                 }
             }
             None => allocator.fallback_alloc(layout),
+        };
+
+        if !ptr.is_null() {
+            allocator.usage.record_alloc(layout.size());
         }
+        ptr
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         let mut allocator = self.lock();
+        allocator.usage.record_dealloc(layout.size());
         match list_index(&layout) {
             Some(index) => {
                 let new_node = ListNode {
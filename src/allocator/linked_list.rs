@@ -5,7 +5,7 @@ use core::{
 
 use linked_list_allocator::align_up;
 
-use super::Locked;
+use super::{HeapStats, Locked, Usage};
 
 // 链表分配器
 struct ListNode {
@@ -27,39 +27,91 @@ impl ListNode {
     }
 }
 
+// 查找空闲区域的策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FitStrategy {
+    FirstFit, // 使用第一个满足要求的区域
+    BestFit,  // 使用满足要求的最小区域
+}
+
 pub struct LinkedListAllocator {
-    head: ListNode,
+    head: ListNode, // 空闲链表按地址升序排列
+    heap_size: usize,
+    strategy: FitStrategy,
+    usage: Usage,
 }
 
 impl LinkedListAllocator {
     pub const fn new() -> Self {
+        Self::with_strategy(FitStrategy::FirstFit)
+    }
+
+    pub const fn with_strategy(strategy: FitStrategy) -> Self {
         Self {
             head: ListNode::new(0),
+            heap_size: 0,
+            strategy,
+            usage: Usage::new(),
         }
     }
 
     pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.heap_size = heap_size;
         self.add_free_region(heap_start, heap_size);
     }
 
-    // 实现对堆链表的push操作
+    // 按地址顺序将空闲区域插入链表, 并与相邻的空闲区域合并
     unsafe fn add_free_region(&mut self, addr: usize, size: usize) {
         // 确保空闲区域有存储ListNode所需的空间和对齐方式
         assert_eq!(align_up(addr, mem::align_of::<ListNode>()), addr);
         assert!(size >= mem::size_of::<ListNode>());
 
-        let mut node = ListNode::new(size);
-        node.next = self.head.next.take();
-        let node_ptr = addr as *mut ListNode;
-        node_ptr.write(node);
-        self.head.next = Some(&mut *node_ptr)
+        // 找到最后一个起始地址小于addr的节点
+        let mut current = &mut self.head;
+        let mut is_head = true;
+        while current.next.as_ref().map_or(false, |next| next.start_addr() < addr) {
+            current = current.next.as_mut().unwrap();
+            is_head = false;
+        }
+
+        // 与后一个区域相邻时合并
+        let mut size = size;
+        let mut next = current.next.take();
+        if let Some(region) = next.take() {
+            if addr + size == region.start_addr() {
+                size += region.size;
+                next = region.next.take();
+            } else {
+                next = Some(region);
+            }
+        }
+
+        // 与前一个区域相邻时合并, 否则插入新节点
+        if !is_head && current.end_addr() == addr {
+            current.size += size;
+            current.next = next;
+        } else {
+            let mut node = ListNode::new(size);
+            node.next = next;
+            let node_ptr = addr as *mut ListNode;
+            node_ptr.write(node);
+            current.next = Some(&mut *node_ptr)
+        }
     }
 
     // 查找条目, 并将查找到的节点从链表中清空
     fn find_region(&mut self, size: usize, align: usize) -> Option<(&'static mut ListNode, usize)> {
+        // 最佳适配时先找出满足要求的最小区域
+        let best = match self.strategy {
+            FitStrategy::FirstFit => None,
+            FitStrategy::BestFit => Some(self.best_fit_region(size, align)?),
+        };
+
         let mut current = &mut self.head;
         while let Some(ref mut region) = current.next {
-            if let Ok(alloc_start) = Self::alloc_from_region(&region, size, align) {
+            let candidate = best.map_or(true, |addr| region.start_addr() == addr);
+            let alloc_start = Self::alloc_from_region(region, size, align);
+            if let (true, Ok(alloc_start)) = (candidate, alloc_start) {
                 let next = region.next.take();
                 let ret = Some((current.next.take().unwrap(), alloc_start));
                 current.next = next;
@@ -73,9 +125,30 @@ impl LinkedListAllocator {
         None
     }
 
+    // 返回满足要求的最小空闲区域的起始地址
+    fn best_fit_region(&self, size: usize, align: usize) -> Option<usize> {
+        let mut best: Option<(usize, usize)> = None;
+        let mut current = &self.head.next;
+        while let Some(region) = current {
+            let fits = Self::alloc_from_region(region, size, align).is_ok();
+            if fits && best.map_or(true, |(_, best_size)| region.size < best_size) {
+                best = Some((region.start_addr(), region.size));
+            }
+            current = &region.next;
+        }
+
+        best.map(|(addr, _)| addr)
+    }
+
     // 检查是否具有指定大小的对齐方式和分配空间
+    // 对齐产生的前部空隙需要能放下一个ListNode, 以便归还给空闲链表
     fn alloc_from_region(region: &ListNode, size: usize, align: usize) -> Result<usize, ()> {
-        let alloc_start = align_up(region.start_addr(), align);
+        let mut alloc_start = align_up(region.start_addr(), align);
+        if alloc_start != region.start_addr()
+            && alloc_start - region.start_addr() < mem::size_of::<ListNode>()
+        {
+            alloc_start = align_up(region.start_addr() + mem::size_of::<ListNode>(), align);
+        }
         let alloc_end = alloc_start.checked_add(size).ok_or(())?;
 
         if alloc_end > region.end_addr() {
@@ -101,6 +174,22 @@ impl LinkedListAllocator {
     }
 }
 
+impl Locked<LinkedListAllocator> {
+    pub fn stats(&self) -> HeapStats {
+        let allocator = self.lock();
+        let mut stats = allocator.usage.stats(allocator.heap_size);
+
+        let mut current = &allocator.head.next;
+        while let Some(region) = current {
+            stats.free_regions += 1;
+            stats.largest_free_region = stats.largest_free_region.max(region.size);
+            current = &region.next;
+        }
+
+        stats
+    }
+}
+
 unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
     unsafe fn alloc(&self, layout: core::alloc::Layout) -> *mut u8 {
         let (size, align) = LinkedListAllocator::size_align(layout);
@@ -109,10 +198,15 @@ unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
         if let Some((region, alloc_start)) = allocator.find_region(size, align) {
             let alloc_end = alloc_start.checked_add(size).expect("overflow");
             let excess_size = region.end_addr() - alloc_end;
+            let padding = alloc_start - region.start_addr();
 
+            if padding > 0 {
+                allocator.add_free_region(region.start_addr(), padding);
+            }
             if excess_size > 0 {
                 allocator.add_free_region(alloc_end, excess_size);
             }
+            allocator.usage.record_alloc(layout.size());
             alloc_start as *mut u8
         } else {
             ptr::null_mut()
@@ -121,7 +215,9 @@ unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: core::alloc::Layout) {
         let (size, _) = LinkedListAllocator::size_align(layout);
+        let mut allocator = self.lock();
 
-        self.lock().add_free_region(ptr as usize, size)
+        allocator.usage.record_dealloc(layout.size());
+        allocator.add_free_region(ptr as usize, size)
     }
 }
@@ -0,0 +1,278 @@
+use core::{
+    alloc::Layout,
+    mem,
+    ptr::{self, NonNull},
+};
+
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, PageSize, PhysFrame, Size4KiB,
+};
+
+use crate::memory;
+
+// 每个slab占用一个物理页
+const SLAB_SIZE: usize = Size4KiB::SIZE as usize;
+
+// slab头部, 存放在slab所在页的起始位置, 其后为对象槽
+struct Slab {
+    next: *mut Slab,
+    prev: *mut Slab,
+    free: *mut u8,    // 第一个空闲对象
+    in_use: usize,    // 已分配的对象数量
+    frame: PhysFrame, // slab所在的物理帧
+}
+
+// slab双向链表
+struct SlabList {
+    head: *mut Slab,
+    len: usize,
+}
+
+impl SlabList {
+    const fn new() -> Self {
+        SlabList {
+            head: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    unsafe fn push(&mut self, slab: *mut Slab) {
+        (*slab).prev = ptr::null_mut();
+        (*slab).next = self.head;
+        if !self.head.is_null() {
+            (*self.head).prev = slab;
+        }
+        self.head = slab;
+        self.len += 1;
+    }
+
+    unsafe fn remove(&mut self, slab: *mut Slab) {
+        if (*slab).prev.is_null() {
+            self.head = (*slab).next;
+        } else {
+            (*(*slab).prev).next = (*slab).next;
+        }
+        if !(*slab).next.is_null() {
+            (*(*slab).next).prev = (*slab).prev;
+        }
+        self.len -= 1;
+    }
+
+    fn pop(&mut self) -> Option<*mut Slab> {
+        let slab = self.head;
+        if slab.is_null() {
+            return None;
+        }
+        unsafe { self.remove(slab) };
+        Some(slab)
+    }
+}
+
+// 对象缓存的统计信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub name: &'static str,
+    pub object_size: usize,
+    pub objects_per_slab: usize,
+    pub objects_in_use: usize,
+    pub partial_slabs: usize,
+    pub full_slabs: usize,
+    pub empty_slabs: usize,
+}
+
+// 同一类对象的slab缓存
+// 空闲链表指针存放在每个对象之后, 因此释放后的对象仍保持构造后的状态,
+// 构造函数只在slab创建时对每个对象调用一次
+pub struct ObjectCache {
+    name: &'static str,
+    layout: Layout,
+    link_offset: usize,  // 对象内空闲链表指针的偏移
+    slot_size: usize,    // 每个对象槽的大小
+    first_offset: usize, // 第一个对象槽相对slab起始的偏移
+    objects_per_slab: usize,
+    constructor: Option<fn(*mut u8)>,
+    partial: SlabList,
+    full: SlabList,
+    empty: SlabList,
+    objects_in_use: usize,
+}
+
+// 裸指针只在持有缓存的锁时访问
+unsafe impl Send for ObjectCache {}
+
+const fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
+impl ObjectCache {
+    pub const fn new(name: &'static str, layout: Layout, constructor: Option<fn(*mut u8)>) -> Self {
+        let align = if layout.align() > mem::align_of::<*mut u8>() {
+            layout.align()
+        } else {
+            mem::align_of::<*mut u8>()
+        };
+        let link_offset = align_up(layout.size(), mem::align_of::<*mut u8>());
+        let slot_size = align_up(link_offset + mem::size_of::<*mut u8>(), align);
+        let first_offset = align_up(mem::size_of::<Slab>(), align);
+        assert!(first_offset + slot_size <= SLAB_SIZE, "object too large for slab");
+
+        ObjectCache {
+            name,
+            layout,
+            link_offset,
+            slot_size,
+            first_offset,
+            objects_per_slab: (SLAB_SIZE - first_offset) / slot_size,
+            constructor,
+            partial: SlabList::new(),
+            full: SlabList::new(),
+            empty: SlabList::new(),
+            objects_in_use: 0,
+        }
+    }
+
+    // 创建存放类型T的缓存
+    pub const fn of<T>(name: &'static str, constructor: Option<fn(*mut u8)>) -> Self {
+        Self::new(name, Layout::new::<T>(), constructor)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            name: self.name,
+            object_size: self.layout.size(),
+            objects_per_slab: self.objects_per_slab,
+            objects_in_use: self.objects_in_use,
+            partial_slabs: self.partial.len,
+            full_slabs: self.full.len,
+            empty_slabs: self.empty.len,
+        }
+    }
+
+    // 分配一个对象, 优先使用部分使用的slab, 其次是空slab, 最后创建新的slab
+    pub fn alloc(&mut self) -> Option<NonNull<u8>> {
+        let slab = if !self.partial.head.is_null() {
+            self.partial.head
+        } else {
+            let slab = match self.empty.pop() {
+                Some(slab) => slab,
+                None => self.grow()?,
+            };
+            unsafe { self.partial.push(slab) };
+            slab
+        };
+
+        unsafe {
+            let object = (*slab).free;
+            (*slab).free = self.link(object).read();
+            (*slab).in_use += 1;
+
+            if (*slab).in_use == self.objects_per_slab {
+                self.partial.remove(slab);
+                self.full.push(slab);
+            }
+
+            self.objects_in_use += 1;
+            NonNull::new(object)
+        }
+    }
+
+    // 释放一个对象
+    // 调用者需保证ptr由该缓存的alloc分配且未被释放
+    pub unsafe fn free(&mut self, ptr: NonNull<u8>) {
+        let object = ptr.as_ptr();
+        let slab = (object as usize & !(SLAB_SIZE - 1)) as *mut Slab;
+
+        let was_full = (*slab).in_use == self.objects_per_slab;
+        self.link(object).write((*slab).free);
+        (*slab).free = object;
+        (*slab).in_use -= 1;
+        self.objects_in_use -= 1;
+
+        // 根据使用情况将slab移动到对应的链表
+        if was_full {
+            self.full.remove(slab);
+        } else {
+            self.partial.remove(slab);
+        }
+        if (*slab).in_use == 0 {
+            self.empty.push(slab);
+        } else {
+            self.partial.push(slab);
+        }
+    }
+
+    // 将所有空slab归还给帧分配器, 返回释放的页数
+    pub fn reclaim(&mut self) -> usize {
+        let mut kernel_memory = memory::lock_kernel_memory();
+        let kernel_memory = match kernel_memory.as_mut() {
+            Some(kernel_memory) => kernel_memory,
+            None => return 0,
+        };
+
+        let mut count = 0;
+        while let Some(slab) = self.empty.pop() {
+            unsafe {
+                kernel_memory
+                    .frame_allocator
+                    .deallocate_frame((*slab).frame)
+            };
+            count += 1;
+        }
+
+        count
+    }
+
+    // 对象的空闲链表指针
+    fn link(&self, object: *mut u8) -> *mut *mut u8 {
+        (object as usize + self.link_offset) as *mut *mut u8
+    }
+
+    // 从帧分配器获取一页并初始化为新的slab
+    // 构造函数可能会分配内存, 因此在释放KERNEL_MEMORY之后才调用
+    fn grow(&mut self) -> Option<*mut Slab> {
+        let (frame, phys_offset) = {
+            let mut kernel_memory = memory::lock_kernel_memory();
+            let kernel_memory = kernel_memory.as_mut()?;
+            let frame = kernel_memory.frame_allocator.allocate_frame()?;
+            (frame, kernel_memory.mapper.phys_offset())
+        };
+        let page = phys_offset + frame.start_address().as_u64();
+        let slab: *mut Slab = page.as_mut_ptr();
+
+        // 从后向前构造对象并串成空闲链表, 使链表按地址升序排列
+        let mut free = ptr::null_mut();
+        for index in (0..self.objects_per_slab).rev() {
+            let object = (slab as usize + self.first_offset + index * self.slot_size) as *mut u8;
+            if let Some(constructor) = self.constructor {
+                constructor(object);
+            }
+            unsafe { self.link(object).write(free) };
+            free = object;
+        }
+
+        unsafe {
+            slab.write(Slab {
+                next: ptr::null_mut(),
+                prev: ptr::null_mut(),
+                free,
+                in_use: 0,
+                frame,
+            })
+        };
+        Some(slab)
+    }
+}
+
+// 缓存销毁时归还空slab, 仍在使用的对象所在的slab无法归还
+impl Drop for ObjectCache {
+    fn drop(&mut self) {
+        if self.empty.len > 0 {
+            self.reclaim();
+        }
+        assert_eq!(
+            self.objects_in_use, 0,
+            "object cache {} dropped with objects in use",
+            self.name
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use core::{arch::asm, fmt};
+
+use x86_64::VirtAddr;
+
+use crate::memory;
+
+pub mod symbols;
+
+// 最多回溯的栈帧数
+const MAX_DEPTH: usize = 32;
+
+// 沿RBP链得到的调用栈, 内核以force-frame-pointers编译, 每个栈帧的[rbp]为上一层的rbp, [rbp+8]为返回地址
+#[derive(Debug, Clone, Copy)]
+pub struct Backtrace {
+    addresses: [u64; MAX_DEPTH],
+    len: usize,
+    exact_first: bool, // 第一个地址是出错指令本身而不是返回地址
+}
+
+impl Backtrace {
+    // 回溯调用者的调用栈, 第一个地址位于调用者中
+    #[inline(never)]
+    pub fn capture() -> Backtrace {
+        let rbp: u64;
+        unsafe { asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags)) };
+        let mut backtrace = Backtrace::empty();
+        backtrace.walk(rbp);
+        backtrace
+    }
+
+    // 回溯异常发生时的调用栈, rip和rbp取自保存的寄存器
+    pub fn from_context(rip: u64, rbp: u64) -> Backtrace {
+        let mut backtrace = Backtrace::empty();
+        backtrace.addresses[0] = rip;
+        backtrace.len = 1;
+        backtrace.exact_first = true;
+        backtrace.walk(rbp);
+        backtrace
+    }
+
+    fn empty() -> Backtrace {
+        Backtrace {
+            addresses: [0; MAX_DEPTH],
+            len: 0,
+            exact_first: false,
+        }
+    }
+
+    fn walk(&mut self, mut rbp: u64) {
+        // 未初始化内存管理时无法检查栈帧是否已映射
+        let offset = match memory::physical_memory_offset() {
+            Some(offset) => offset,
+            None => return,
+        };
+
+        while self.len < MAX_DEPTH {
+            if rbp == 0 || rbp & 7 != 0 || !is_mapped(rbp, offset) || !is_mapped(rbp + 8, offset) {
+                break;
+            }
+            let frame = rbp as *const u64;
+            let (next, return_address) = unsafe { (frame.read(), frame.add(1).read()) };
+            if return_address == 0 {
+                break;
+            }
+            self.addresses[self.len] = return_address;
+            self.len += 1;
+
+            // 栈向低地址增长, 上一层的栈帧一定位于更高的地址
+            if next <= rbp {
+                break;
+            }
+            rbp = next;
+        }
+    }
+
+    // 回溯得到的地址, 除异常的出错指令外都是返回地址
+    pub fn addresses(&self) -> &[u64] {
+        &self.addresses[..self.len]
+    }
+
+    // 第index个地址所在的函数
+    pub fn symbol(&self, index: usize) -> Option<symbols::Symbol> {
+        symbols::resolve(self.lookup_address(index)?)
+    }
+
+    // 返回地址指向call的下一条指令, 减1后才落在调用者的call指令内
+    fn lookup_address(&self, index: usize) -> Option<u64> {
+        let addr = *self.addresses().get(index)?;
+        match index == 0 && self.exact_first {
+            true => Some(addr),
+            false => Some(addr - 1),
+        }
+    }
+}
+
+fn is_mapped(addr: u64, physical_memory_offset: VirtAddr) -> bool {
+    VirtAddr::try_new(addr)
+        .ok()
+        .and_then(|addr| memory::translate_addr(addr, physical_memory_offset))
+        .is_some()
+}
+
+// 每行输出一个栈帧的地址及其所在的函数
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.len == 0 {
+            return write!(f, "  <backtrace unavailable>");
+        }
+        for (index, &addr) in self.addresses().iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "  #{:<2} {:#018x}", index, addr)?;
+            match self.symbol(index) {
+                Some(symbol) => write!(f, " {}+{:#x}", symbol.name, addr - symbol.addr)?,
+                None => write!(f, " <unknown>")?,
+            }
+        }
+        Ok(())
+    }
+}
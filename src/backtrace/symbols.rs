@@ -0,0 +1,90 @@
+use core::{mem, ptr, slice, str};
+
+const MAGIC: [u8; 8] = *b"KSYMTAB\0";
+const HEADER_SIZE: usize = 16;
+// 预留给符号表的空间, 由build.rs按tools/ksymtab上次统计的符号表大小生成
+// 装不下的符号会被tools/ksymtab丢弃, 下一次构建时会按所需的大小重新分配
+include!(concat!(env!("OUT_DIR"), "/ksymtab_size.rs"));
+
+// 格式见tools/ksymtab/src/main.rs
+#[repr(C, align(8))]
+struct SymbolTable {
+    magic: [u8; 8],
+    count: u32,
+    strings_offset: u32, // 相对于符号表的起始
+    data: [u8; TABLE_SIZE - HEADER_SIZE],
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct Entry {
+    addr: u64,
+    size: u32,
+    name_offset: u32, // 相对于符号名区域的起始
+    name_len: u32,
+    _padding: u32,
+}
+
+// 编译时只写入魔数, 链接后由runner tools/ksymtab填入内核的函数符号
+// 导出该符号使编译器不会把其中的内容当作常量
+#[no_mangle]
+#[link_section = ".ksymtab"]
+static mut KERNEL_SYMBOL_TABLE: SymbolTable = SymbolTable {
+    magic: MAGIC,
+    count: 0,
+    strings_offset: HEADER_SIZE as u32,
+    data: [0; TABLE_SIZE - HEADER_SIZE],
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: &'static str,
+    pub addr: u64, // 函数的起始地址
+}
+
+fn table() -> &'static SymbolTable {
+    unsafe { &*ptr::addr_of!(KERNEL_SYMBOL_TABLE) }
+}
+
+fn entries() -> &'static [Entry] {
+    let table = table();
+    if table.magic != MAGIC {
+        return &[];
+    }
+    let max = table.data.len() / mem::size_of::<Entry>();
+    let count = (table.count as usize).min(max);
+    unsafe { slice::from_raw_parts(table.data.as_ptr() as *const Entry, count) }
+}
+
+// 符号表中的函数个数, 为0说明内核没有经过tools/ksymtab处理
+pub fn symbol_count() -> usize {
+    entries().len()
+}
+
+// 查找包含addr的函数
+pub fn resolve(addr: u64) -> Option<Symbol> {
+    let entries = entries();
+    let index = match entries.binary_search_by_key(&addr, |entry| entry.addr) {
+        Ok(index) => index,
+        Err(0) => return None,
+        Err(index) => index - 1,
+    };
+    let entry = entries[index];
+    // 大小为0的符号(例如汇编中的标签)视为一直延伸到下一个符号
+    let end = match entry.size {
+        0 => entries.get(index + 1)?.addr,
+        size => entry.addr + u64::from(size),
+    };
+    if addr >= end {
+        return None;
+    }
+
+    let table = table();
+    let start =
+        (table.strings_offset as usize).checked_sub(HEADER_SIZE)? + entry.name_offset as usize;
+    let name = table.data.get(start..start + entry.name_len as usize)?;
+    Some(Symbol {
+        name: str::from_utf8(name).ok()?,
+        addr: entry.addr,
+    })
+}
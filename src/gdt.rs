@@ -1,21 +1,36 @@
+use core::ptr;
+
 use lazy_static::lazy_static;
 use x86_64::registers::segmentation::Segment;
 use x86_64::structures::gdt::{GlobalDescriptorTable, Descriptor, SegmentSelector};
 use x86_64::structures::tss::TaskStateSegment;
 use x86_64::VirtAddr;
 
+use crate::memory::vma::{self, VmaError};
+
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
 
+const STACK_SIZE: usize = 4096 * 5;
+const GUARD_SIZE: usize = 4096;
+
+// 中断栈, 最低的一页在protect_stacks中取消映射作为保护页
+// 只有双重错误使用IST, 内核栈溢出引起的页错误会升级为双重错误并在这里处理
+#[repr(align(4096))]
+struct Stack([u8; GUARD_SIZE + STACK_SIZE]);
+
+static mut DOUBLE_FAULT_STACK: Stack = Stack([0; GUARD_SIZE + STACK_SIZE]);
+
+fn stack_bottom(stack: *const Stack) -> VirtAddr {
+    VirtAddr::from_ptr(stack)
+}
+
 lazy_static! {
     // 任务状态段
     static ref TSS: TaskStateSegment = {
         let mut tss = TaskStateSegment::new();
         tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096 * 5;
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-
-            let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
-            let stack_end = stack_start + STACK_SIZE;
+            let stack_start = stack_bottom(unsafe { ptr::addr_of!(DOUBLE_FAULT_STACK) });
+            let stack_end = stack_start + GUARD_SIZE + STACK_SIZE;
             stack_end
         };
         tss
@@ -41,6 +56,14 @@ pub fn init() {
     }
 }
 
+// 取消中断栈保护页的映射并登记到内核虚拟地址管理器
+// 由memory::init_kernel_memory调用
+pub fn protect_stacks() -> Result<(), VmaError> {
+    let stack = unsafe { ptr::addr_of!(DOUBLE_FAULT_STACK) };
+    let size = (GUARD_SIZE + STACK_SIZE) as u64;
+    unsafe { vma::guard_existing_stack("double fault stack", stack_bottom(stack), size) }
+}
+
 struct Selectors {
     code_selector: SegmentSelector,
     tss_selector: SegmentSelector,
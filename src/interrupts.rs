@@ -5,27 +5,22 @@ use pc_keyboard::Keyboard;
 use pc_keyboard::ScancodeSet1;
 use pic8259::ChainedPics;
 use spin;
-use x86_64::structures::idt::PageFaultErrorCode;
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
 
-use crate::gdt;
-use crate::hlt_loop;
 use crate::print;
-use crate::warn;
+
+pub mod apic;
+pub mod context;
+pub mod exceptions;
+pub mod irq;
 
 lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
-        idt.breakpoint.set_handler_fn(breakpoint_handler);
-        idt.page_fault.set_handler_fn(page_fault_handler);
-        unsafe {
-            idt.double_fault
-                .set_handler_fn(double_fault_handler)
-                .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
-        }
+        exceptions::install(&mut idt);
 
-        idt[InterruptIndex::Timer.as_usize()].set_handler_fn(timer_interrupt_handler);
-        idt[InterruptIndex::Keyboard.as_usize()].set_handler_fn(keyboard_interrupt_handler);
+        irq::install(&mut idt);
+        idt[usize::from(apic::SPURIOUS_VECTOR)].set_handler_fn(spurious_interrupt_handler);
         idt
     };
 }
@@ -40,44 +35,21 @@ pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 pub static PICS: spin::Mutex<ChainedPics> =
     spin::Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
 
-extern "x86-interrupt" fn breakpoint_handler(stack_fram: InterruptStackFrame) {
-    warn!("EXCEPTION: BREAKPOINT\n{:#?}", stack_fram);
-}
-
-// 内存越界访问时触发该异常
-extern "x86-interrupt" fn page_fault_handler(
-    stack_frame: InterruptStackFrame,
-    error_code: PageFaultErrorCode,
-) {
-    use x86_64::registers::control::Cr2;
-
-    warn!("EXCEPTION: PAGE FAULT");
-    warn!("Accessed Address: {:?}", Cr2::read());
-    warn!("Error Code: {:?}", error_code);
-    warn!("{:#?}", stack_frame);
-
-    hlt_loop();
-}
-
-extern "x86-interrupt" fn double_fault_handler(
-    stack_fram: InterruptStackFrame,
-    _error_code: u64,
-) -> ! {
-    panic!("EXCEPTION: BREAKPOINT\n{:#?}", stack_fram);
+// 注册内核自带的时钟和键盘中断处理函数, 需要在初始化8259之后调用
+pub fn init_irqs() {
+    irq::register_irq(InterruptIndex::Timer.irq(), timer_interrupt_handler)
+        .expect("failed to register timer interrupt");
+    irq::register_irq(InterruptIndex::Keyboard.irq(), keyboard_interrupt_handler)
+        .expect("failed to register keyboard interrupt");
 }
 
 // 时钟中断
-extern "x86-interrupt" fn timer_interrupt_handler(_stack_fram: InterruptStackFrame) {
+fn timer_interrupt_handler(_irq: u8) {
     print!(".");
-
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
-    }
 }
 
 // 键盘中断
-extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_fram: InterruptStackFrame) {
+fn keyboard_interrupt_handler(_irq: u8) {
     use pc_keyboard::layouts;
     use spin::Mutex;
     use x86_64::instructions::port::Port;
@@ -100,13 +72,11 @@ extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_fram: InterruptStack
             }
         }
     }
-
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Keyboard.as_u8());
-    }
 }
 
+// 本地APIC的伪中断, 不需要EOI
+extern "x86-interrupt" fn spurious_interrupt_handler(_stack_frame: InterruptStackFrame) {}
+
 #[test_case]
 fn test_breakpoint_execption() {
     x86_64::instructions::interrupts::int3();
@@ -125,7 +95,8 @@ impl InterruptIndex {
         self as u8
     }
 
-    fn as_usize(self) -> usize {
-        usize::from(self.as_u8())
+    // 对应的ISA中断线, 无论使用8259还是APIC, 中断线irq都投递到向量PIC_1_OFFSET + irq
+    pub fn irq(self) -> u8 {
+        self.as_u8() - PIC_1_OFFSET
     }
 }
@@ -0,0 +1,251 @@
+use alloc::vec::Vec;
+use core::arch::x86_64::__cpuid;
+
+use spin::{Mutex, Once};
+use x86_64::{instructions::interrupts, registers::model_specific::Msr, PhysAddr};
+
+use super::{
+    irq::{self, IRQ_LINES},
+    PICS, PIC_1_OFFSET,
+};
+use crate::{
+    acpi::{self, AcpiError, IoApicEntry, Madt},
+    memory::{
+        mmio::{map_mmio, MmioRegion},
+        vma::VmaError,
+    },
+};
+
+const IA32_APIC_BASE: u32 = 0x1b;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+
+// 本地APIC寄存器的偏移
+const LAPIC_ID: usize = 0x20;
+const LAPIC_TASK_PRIORITY: usize = 0x80;
+const LAPIC_EOI: usize = 0xb0;
+const LAPIC_SPURIOUS: usize = 0xf0;
+const LAPIC_SOFTWARE_ENABLE: u32 = 1 << 8;
+const LAPIC_SIZE: usize = 0x400;
+
+// 本地APIC的伪中断向量, 该中断不需要EOI
+pub const SPURIOUS_VECTOR: u8 = 0xff;
+
+// I/O APIC通过选择寄存器和数据窗口间接访问内部寄存器
+const IOAPIC_SELECT: usize = 0x00;
+const IOAPIC_WINDOW: usize = 0x10;
+const IOAPIC_SIZE: usize = 0x20;
+const IOAPIC_VERSION: u32 = 0x01;
+const IOAPIC_REDIRECTION_TABLE: u32 = 0x10;
+
+// 重定向表项的标志, 未设置的位表示固定投递, 物理目标模式, 高电平有效, 边沿触发
+const REDIRECTION_ACTIVE_LOW: u64 = 1 << 13;
+const REDIRECTION_LEVEL_TRIGGERED: u64 = 1 << 15;
+const REDIRECTION_MASKED: u64 = 1 << 16;
+
+#[derive(Debug)]
+pub enum ApicError {
+    Acpi(AcpiError),
+    NoIoApic,
+    Mmio(VmaError),
+    IrqNotRouted(u8), // 没有I/O APIC负责该中断线
+    GsiInUse(u32),    // 该全局系统中断已被其他中断线的重定向占用
+}
+
+pub struct LocalApic {
+    mmio: MmioRegion,
+}
+
+impl LocalApic {
+    fn read(&self, offset: usize) -> u32 {
+        self.mmio.read32(offset)
+    }
+
+    // 中断处理中只能拿到共享引用
+    fn write(&self, offset: usize, value: u32) {
+        self.mmio.write32(offset, value)
+    }
+
+    pub fn id(&self) -> u8 {
+        (self.read(LAPIC_ID) >> 24) as u8
+    }
+
+    pub fn end_of_interrupt(&self) {
+        self.write(LAPIC_EOI, 0);
+    }
+}
+
+struct IoApic {
+    mmio: MmioRegion,
+    gsi_base: u32,
+    entries: u32, // 重定向表项的个数
+}
+
+impl IoApic {
+    fn new(entry: &IoApicEntry) -> Result<IoApic, ApicError> {
+        let mmio = map_mmio(PhysAddr::new(entry.address), IOAPIC_SIZE).map_err(ApicError::Mmio)?;
+        let mut io_apic = IoApic {
+            mmio,
+            gsi_base: entry.gsi_base,
+            entries: 0,
+        };
+        io_apic.entries = ((io_apic.read(IOAPIC_VERSION) >> 16) & 0xff) + 1;
+        Ok(io_apic)
+    }
+
+    fn read(&mut self, register: u32) -> u32 {
+        self.mmio.write(IOAPIC_SELECT, register);
+        self.mmio.read(IOAPIC_WINDOW)
+    }
+
+    fn write(&mut self, register: u32, value: u32) {
+        self.mmio.write(IOAPIC_SELECT, register);
+        self.mmio.write(IOAPIC_WINDOW, value);
+    }
+
+    fn handles(&self, gsi: u32) -> bool {
+        self.gsi_base <= gsi && gsi < self.gsi_base + self.entries
+    }
+
+    // 先写入目标所在的高32位, 最后写入包含屏蔽位的低32位
+    fn set_redirection(&mut self, gsi: u32, entry: u64) {
+        let register = IOAPIC_REDIRECTION_TABLE + (gsi - self.gsi_base) * 2;
+        self.write(register + 1, (entry >> 32) as u32);
+        self.write(register, entry as u32);
+    }
+}
+
+// 系统中的所有I/O APIC以及ISA中断线的重定向信息
+struct IoApics {
+    io_apics: Vec<IoApic>,
+    madt: Madt,
+    destination: u8, // 接收中断的本地APIC
+}
+
+impl IoApics {
+    // 将ISA中断线irq路由到向量PIC_1_OFFSET + irq所需的重定向表项, 与8259重映射后的向量一致
+    // 返回负责该中断的I/O APIC, 全局系统中断号和表项, 不修改硬件
+    fn redirection(&self, irq: u8, masked: bool) -> Result<(usize, u32, u64), ApicError> {
+        let mut entry = u64::from(PIC_1_OFFSET + irq) | u64::from(self.destination) << 56;
+        let gsi = match self.madt.override_for(irq) {
+            Some(over) => {
+                if over.active_low() {
+                    entry |= REDIRECTION_ACTIVE_LOW;
+                }
+                if over.level_triggered() {
+                    entry |= REDIRECTION_LEVEL_TRIGGERED;
+                }
+                over.gsi
+            }
+            None => u32::from(irq),
+        };
+        // 例如时钟中断通常被重定向到GSI 2, 不能再把中断线2路由到同一个表项
+        let claimed = self
+            .madt
+            .overrides
+            .iter()
+            .any(|over| over.source != irq && over.gsi == gsi);
+        if claimed {
+            return Err(ApicError::GsiInUse(gsi));
+        }
+        if masked {
+            entry |= REDIRECTION_MASKED;
+        }
+
+        let index = self
+            .io_apics
+            .iter()
+            .position(|io_apic| io_apic.handles(gsi))
+            .ok_or(ApicError::IrqNotRouted(irq))?;
+        Ok((index, gsi, entry))
+    }
+
+    fn route(&mut self, irq: u8, masked: bool) -> Result<(), ApicError> {
+        let (index, gsi, entry) = self.redirection(irq, masked)?;
+        self.io_apics[index].set_redirection(gsi, entry);
+        Ok(())
+    }
+}
+
+static LOCAL_APIC: Once<LocalApic> = Once::new();
+static IO_APICS: Mutex<Option<IoApics>> = Mutex::new(None);
+
+// APIC启用后返回本地APIC
+pub fn local_apic() -> Option<&'static LocalApic> {
+    LOCAL_APIC.r#try()
+}
+
+pub fn is_enabled() -> bool {
+    local_apic().is_some()
+}
+
+// 在I/O APIC上屏蔽或打开ISA中断线irq
+pub fn set_irq_masked(irq: u8, masked: bool) -> Result<(), ApicError> {
+    match IO_APICS.lock().as_mut() {
+        Some(io_apics) => io_apics.route(irq, masked),
+        None => Err(ApicError::IrqNotRouted(irq)),
+    }
+}
+
+// 屏蔽8259并改用APIC: 启用本地APIC, 通过MADT找到I/O APIC并将已注册处理函数的中断线路由到原来的向量
+// 需要先初始化堆和内核内存以便映射寄存器
+// 路由表在修改任何硬件之前生成并检查, 失败时8259和本地APIC保持原样, 可以继续使用8259
+pub fn init() -> Result<(), ApicError> {
+    if is_enabled() {
+        return Ok(());
+    }
+
+    let madt = acpi::madt().map_err(ApicError::Acpi)?;
+    if madt.io_apics.is_empty() {
+        return Err(ApicError::NoIoApic);
+    }
+    let local_apic = LocalApic {
+        mmio: map_mmio(PhysAddr::new(madt.local_apic_address), LAPIC_SIZE)
+            .map_err(ApicError::Mmio)?,
+    };
+    let io_apics = madt
+        .io_apics
+        .iter()
+        .map(IoApic::new)
+        .collect::<Result<Vec<_>, _>>()?;
+    // 本地APIC启用前从CPUID读取当前处理器的APIC ID
+    let destination = (unsafe { __cpuid(1) }.ebx >> 24) as u8;
+    let mut routing = IoApics {
+        io_apics,
+        madt,
+        destination,
+    };
+
+    interrupts::without_interrupts(|| {
+        let redirections = (0..IRQ_LINES as u8)
+            .filter(|&line| irq::handler_count(line) > 0)
+            .map(|line| routing.redirection(line, false))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // 屏蔽I/O APIC的所有表项, 之后只打开已有处理函数的中断线
+        for io_apic in routing.io_apics.iter_mut() {
+            for gsi in io_apic.gsi_base..io_apic.gsi_base + io_apic.entries {
+                io_apic.set_redirection(gsi, REDIRECTION_MASKED);
+            }
+        }
+
+        unsafe {
+            PICS.lock().disable();
+            let mut apic_base = Msr::new(IA32_APIC_BASE);
+            let value = apic_base.read();
+            apic_base.write(value | APIC_BASE_ENABLE);
+        }
+        local_apic.write(LAPIC_TASK_PRIORITY, 0);
+        local_apic.write(
+            LAPIC_SPURIOUS,
+            LAPIC_SOFTWARE_ENABLE | u32::from(SPURIOUS_VECTOR),
+        );
+
+        for (index, gsi, entry) in redirections {
+            routing.io_apics[index].set_redirection(gsi, entry);
+        }
+
+        *IO_APICS.lock() = Some(routing);
+        LOCAL_APIC.call_once(|| local_apic);
+        Ok(())
+    })
+}
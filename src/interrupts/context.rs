@@ -0,0 +1,100 @@
+use core::fmt;
+
+use x86_64::{
+    registers::{
+        control::{Cr0, Cr2, Cr3, Cr4},
+        model_specific::Efer,
+        rflags::RFlags,
+    },
+    structures::idt::InterruptStackFrameValue,
+};
+
+// 异常入口保存的通用寄存器, 顺序与exceptions.rs中入栈的顺序相反
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Registers {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+}
+
+// 异常发生时的完整上下文, 即异常入口执行完毕后栈上的内容
+#[repr(C)]
+pub struct ExceptionContext {
+    pub registers: Registers,
+    pub vector: u64,
+    pub error_code: u64, // 没有错误码的异常为0
+    pub stack_frame: InterruptStackFrameValue,
+}
+
+impl ExceptionContext {
+    pub fn rflags(&self) -> RFlags {
+        RFlags::from_bits_truncate(self.stack_frame.cpu_flags)
+    }
+}
+
+// 输出所有通用寄存器, 段寄存器, RFLAGS以及当前的控制寄存器
+impl fmt::Display for ExceptionContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let r = &self.registers;
+        let frame = &self.stack_frame;
+
+        writeln!(
+            f,
+            "RAX={:016x} RBX={:016x} RCX={:016x}",
+            r.rax, r.rbx, r.rcx
+        )?;
+        writeln!(
+            f,
+            "RDX={:016x} RSI={:016x} RDI={:016x}",
+            r.rdx, r.rsi, r.rdi
+        )?;
+        writeln!(
+            f,
+            "RBP={:016x} RSP={:016x} RIP={:016x}",
+            r.rbp,
+            frame.stack_pointer.as_u64(),
+            frame.instruction_pointer.as_u64()
+        )?;
+        writeln!(f, "R8 ={:016x} R9 ={:016x} R10={:016x}", r.r8, r.r9, r.r10)?;
+        writeln!(
+            f,
+            "R11={:016x} R12={:016x} R13={:016x}",
+            r.r11, r.r12, r.r13
+        )?;
+        writeln!(f, "R14={:016x} R15={:016x}", r.r14, r.r15)?;
+        writeln!(
+            f,
+            "CS={:04x} SS={:04x}",
+            frame.code_segment, frame.stack_segment
+        )?;
+        writeln!(f, "RFLAGS={:016x} {:?}", frame.cpu_flags, self.rflags())?;
+
+        let cr0 = Cr0::read();
+        let (cr3_frame, cr3_flags) = Cr3::read();
+        let cr4 = Cr4::read();
+        let efer = Efer::read();
+        writeln!(f, "CR0={:016x} {:?}", cr0.bits(), cr0)?;
+        writeln!(f, "CR2={:016x}", Cr2::read().as_u64())?;
+        writeln!(
+            f,
+            "CR3={:016x} {:?}",
+            cr3_frame.start_address().as_u64() | cr3_flags.bits(),
+            cr3_flags
+        )?;
+        writeln!(f, "CR4={:016x} {:?}", cr4.bits(), cr4)?;
+        write!(f, "EFER={:016x} {:?}", efer.bits(), efer)
+    }
+}
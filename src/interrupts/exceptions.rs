@@ -0,0 +1,380 @@
+use core::{
+    arch::global_asm,
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use spin::Mutex;
+use x86_64::{
+    registers::control::Cr2,
+    structures::idt::{InterruptDescriptorTable, PageFaultErrorCode, SelectorErrorCode},
+    VirtAddr,
+};
+
+use super::context::{ExceptionContext, Registers};
+use crate::{allocator, backtrace::Backtrace, gdt, memory, report, serial_println};
+
+// 每个异常的入口先压入错误码(没有错误码的异常压入0)和向量号,
+// 再由exception_common保存所有通用寄存器, 以栈上的ExceptionContext调用exception_dispatch
+// exception_dispatch返回时恢复寄存器并从异常返回
+global_asm!(
+    ".macro EXCEPTION_STUB vector",
+    ".global exception_stub_\\vector",
+    "exception_stub_\\vector:",
+    "    push 0",
+    "    push \\vector",
+    "    jmp exception_common",
+    ".endm",
+    ".macro EXCEPTION_STUB_ERROR_CODE vector",
+    ".global exception_stub_\\vector",
+    "exception_stub_\\vector:",
+    "    push \\vector",
+    "    jmp exception_common",
+    ".endm",
+    "EXCEPTION_STUB 0",
+    "EXCEPTION_STUB 1",
+    "EXCEPTION_STUB 2",
+    "EXCEPTION_STUB 3",
+    "EXCEPTION_STUB 4",
+    "EXCEPTION_STUB 5",
+    "EXCEPTION_STUB 6",
+    "EXCEPTION_STUB 7",
+    "EXCEPTION_STUB_ERROR_CODE 8",
+    "EXCEPTION_STUB_ERROR_CODE 10",
+    "EXCEPTION_STUB_ERROR_CODE 11",
+    "EXCEPTION_STUB_ERROR_CODE 12",
+    "EXCEPTION_STUB_ERROR_CODE 13",
+    "EXCEPTION_STUB_ERROR_CODE 14",
+    "EXCEPTION_STUB 16",
+    "EXCEPTION_STUB_ERROR_CODE 17",
+    "EXCEPTION_STUB 18",
+    "EXCEPTION_STUB 19",
+    "EXCEPTION_STUB 20",
+    "EXCEPTION_STUB_ERROR_CODE 21",
+    "EXCEPTION_STUB_ERROR_CODE 29",
+    "EXCEPTION_STUB_ERROR_CODE 30",
+    "exception_common:",
+    "    cld",
+    "    push rax",
+    "    push rbx",
+    "    push rcx",
+    "    push rdx",
+    "    push rsi",
+    "    push rdi",
+    "    push rbp",
+    "    push r8",
+    "    push r9",
+    "    push r10",
+    "    push r11",
+    "    push r12",
+    "    push r13",
+    "    push r14",
+    "    push r15",
+    // CPU进入异常时已将栈对齐到16字节, 之后压入了22个8字节的值, 调用时仍然对齐
+    "    mov rdi, rsp",
+    "    call exception_dispatch",
+    "    pop r15",
+    "    pop r14",
+    "    pop r13",
+    "    pop r12",
+    "    pop r11",
+    "    pop r10",
+    "    pop r9",
+    "    pop r8",
+    "    pop rbp",
+    "    pop rdi",
+    "    pop rsi",
+    "    pop rdx",
+    "    pop rcx",
+    "    pop rbx",
+    "    pop rax",
+    "    add rsp, 16",
+    "    iretq",
+);
+
+extern "C" {
+    fn exception_stub_0();
+    fn exception_stub_1();
+    fn exception_stub_2();
+    fn exception_stub_3();
+    fn exception_stub_4();
+    fn exception_stub_5();
+    fn exception_stub_6();
+    fn exception_stub_7();
+    fn exception_stub_8();
+    fn exception_stub_10();
+    fn exception_stub_11();
+    fn exception_stub_12();
+    fn exception_stub_13();
+    fn exception_stub_14();
+    fn exception_stub_16();
+    fn exception_stub_17();
+    fn exception_stub_18();
+    fn exception_stub_19();
+    fn exception_stub_20();
+    fn exception_stub_21();
+    fn exception_stub_29();
+    fn exception_stub_30();
+}
+
+const DEBUG: u8 = 1;
+const NON_MASKABLE_INTERRUPT: u8 = 2;
+const BREAKPOINT: u8 = 3;
+const DOUBLE_FAULT: u8 = 8;
+const PAGE_FAULT: u8 = 14;
+
+// 最近一次致命异常的向量号和错误码, u64::MAX表示没有
+static LAST_VECTOR: AtomicU64 = AtomicU64::new(u64::MAX);
+static LAST_ERROR_CODE: AtomicU64 = AtomicU64::new(u64::MAX);
+static LAST_REGISTERS: Mutex<Option<Registers>> = Mutex::new(None);
+
+// 返回最近一次致命异常的向量号和错误码, 供panic处理和测试检查
+pub fn last_fatal_exception() -> Option<(u8, Option<u64>)> {
+    let vector = LAST_VECTOR.load(Ordering::Relaxed);
+    if vector == u64::MAX {
+        return None;
+    }
+    let error_code = match LAST_ERROR_CODE.load(Ordering::Relaxed) {
+        u64::MAX => None,
+        code => Some(code),
+    };
+    Some((vector as u8, error_code))
+}
+
+// 最近一次致命异常发生时的通用寄存器
+pub fn last_fatal_registers() -> Option<Registers> {
+    *LAST_REGISTERS.try_lock()?
+}
+
+// 安装所有体系结构异常的入口
+pub(super) fn install(idt: &mut InterruptDescriptorTable) {
+    let addr = |stub: unsafe extern "C" fn()| VirtAddr::new(stub as usize as u64);
+
+    unsafe {
+        idt.divide_error.set_handler_addr(addr(exception_stub_0));
+        idt.debug.set_handler_addr(addr(exception_stub_1));
+        idt.non_maskable_interrupt
+            .set_handler_addr(addr(exception_stub_2));
+        idt.breakpoint.set_handler_addr(addr(exception_stub_3));
+        idt.overflow.set_handler_addr(addr(exception_stub_4));
+        idt.bound_range_exceeded
+            .set_handler_addr(addr(exception_stub_5));
+        idt.invalid_opcode.set_handler_addr(addr(exception_stub_6));
+        idt.device_not_available
+            .set_handler_addr(addr(exception_stub_7));
+        idt.double_fault
+            .set_handler_addr(addr(exception_stub_8))
+            .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+        idt.invalid_tss.set_handler_addr(addr(exception_stub_10));
+        idt.segment_not_present
+            .set_handler_addr(addr(exception_stub_11));
+        idt.stack_segment_fault
+            .set_handler_addr(addr(exception_stub_12));
+        idt.general_protection_fault
+            .set_handler_addr(addr(exception_stub_13));
+        // 页错误不使用IST, 否则处理过程中嵌套的页错误会覆盖外层的异常帧
+        // 内核栈溢出时页错误无法压栈, 会升级为使用独立栈的双重错误
+        idt.page_fault.set_handler_addr(addr(exception_stub_14));
+        idt.x87_floating_point
+            .set_handler_addr(addr(exception_stub_16));
+        idt.alignment_check
+            .set_handler_addr(addr(exception_stub_17));
+        idt.machine_check.set_handler_addr(addr(exception_stub_18));
+        idt.simd_floating_point
+            .set_handler_addr(addr(exception_stub_19));
+        idt.virtualization.set_handler_addr(addr(exception_stub_20));
+        idt.cp_protection_exception
+            .set_handler_addr(addr(exception_stub_21));
+        idt.vmm_communication_exception
+            .set_handler_addr(addr(exception_stub_29));
+        idt.security_exception
+            .set_handler_addr(addr(exception_stub_30));
+    }
+}
+
+// 错误码的解码方式
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ErrorKind {
+    None,
+    Selector, // #TS, #NP, #SS, #GP: 引起异常的段选择子或IDT项
+    PageFault,
+    Raw,
+}
+
+fn describe(vector: u8) -> (&'static str, ErrorKind) {
+    match vector {
+        0 => ("DIVIDE ERROR (#DE)", ErrorKind::None),
+        1 => ("DEBUG (#DB)", ErrorKind::None),
+        2 => ("NON-MASKABLE INTERRUPT", ErrorKind::None),
+        3 => ("BREAKPOINT (#BP)", ErrorKind::None),
+        4 => ("OVERFLOW (#OF)", ErrorKind::None),
+        5 => ("BOUND RANGE EXCEEDED (#BR)", ErrorKind::None),
+        6 => ("INVALID OPCODE (#UD)", ErrorKind::None),
+        7 => ("DEVICE NOT AVAILABLE (#NM)", ErrorKind::None),
+        8 => ("DOUBLE FAULT (#DF)", ErrorKind::Raw),
+        10 => ("INVALID TSS (#TS)", ErrorKind::Selector),
+        11 => ("SEGMENT NOT PRESENT (#NP)", ErrorKind::Selector),
+        12 => ("STACK SEGMENT FAULT (#SS)", ErrorKind::Selector),
+        13 => ("GENERAL PROTECTION FAULT (#GP)", ErrorKind::Selector),
+        14 => ("PAGE FAULT (#PF)", ErrorKind::PageFault),
+        16 => ("X87 FLOATING POINT (#MF)", ErrorKind::None),
+        17 => ("ALIGNMENT CHECK (#AC)", ErrorKind::Raw),
+        18 => ("MACHINE CHECK (#MC)", ErrorKind::None),
+        19 => ("SIMD FLOATING POINT (#XM)", ErrorKind::None),
+        20 => ("VIRTUALIZATION (#VE)", ErrorKind::None),
+        // 只在启用CET影子栈或间接跳转跟踪时产生
+        21 => ("CONTROL PROTECTION (#CP)", ErrorKind::Raw),
+        29 => ("VMM COMMUNICATION (#VC)", ErrorKind::Raw),
+        30 => ("SECURITY EXCEPTION (#SX)", ErrorKind::Raw),
+        _ => ("UNKNOWN EXCEPTION", ErrorKind::Raw),
+    }
+}
+
+struct ErrorCode(ErrorKind, u64);
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ErrorCode(kind, code) = *self;
+        match kind {
+            ErrorKind::None => Ok(()),
+            // 错误码为0表示与特定的选择子无关, 例如访问非规范地址
+            ErrorKind::Selector if code == 0 => write!(f, "0 (no selector)"),
+            ErrorKind::Selector => {
+                let selector = SelectorErrorCode::new_truncate(code);
+                write!(
+                    f,
+                    "{:#x} ({:?} index {:#x}{})",
+                    code,
+                    selector.descriptor_table(),
+                    selector.index(),
+                    if selector.external() {
+                        ", external"
+                    } else {
+                        ""
+                    },
+                )
+            }
+            ErrorKind::PageFault => write!(
+                f,
+                "{:#x} ({:?})",
+                code,
+                PageFaultErrorCode::from_bits_truncate(code)
+            ),
+            ErrorKind::Raw => write!(f, "{:#x}", code),
+        }
+    }
+}
+
+// 由exception_common调用, 返回后从异常返回继续执行
+#[no_mangle]
+extern "C" fn exception_dispatch(context: &mut ExceptionContext) {
+    match context.vector as u8 {
+        // 单步和断点, 报告后继续执行
+        DEBUG | BREAKPOINT => {
+            serial_println!("{}", Report(context));
+        }
+        // 不可屏蔽中断通常来自硬件错误或看门狗, 报告后继续执行
+        NON_MASKABLE_INTERRUPT => report_nmi(context),
+        PAGE_FAULT => page_fault(context),
+        DOUBLE_FAULT => double_fault(context),
+        _ => fatal(context),
+    }
+}
+
+// 异常名称, 解码后的错误码, 所有寄存器以及调用栈
+struct Report<'a>(&'a ExceptionContext);
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let context = self.0;
+        let (name, kind) = describe(context.vector as u8);
+        writeln!(f, "EXCEPTION: {} (vector {})", name, context.vector)?;
+        if kind != ErrorKind::None {
+            writeln!(f, "Error Code: {}", ErrorCode(kind, context.error_code))?;
+        }
+        writeln!(f, "{}", context)?;
+        write!(
+            f,
+            "backtrace:\n{}",
+            Backtrace::from_context(
+                context.stack_frame.instruction_pointer.as_u64(),
+                context.registers.rbp
+            )
+        )
+    }
+}
+
+fn report(context: &ExceptionContext) {
+    report!("{}", Report(context));
+}
+
+// NMI可以打断正持有屏幕或串口锁的代码, 关中断也无法避免
+// 因此只输出到串口, 并在输出前强制释放串口锁
+fn report_nmi(context: &ExceptionContext) {
+    unsafe { crate::serial::SERIAL.force_unlock() };
+    serial_println!("{}", Report(context));
+}
+
+// 报告并记录异常后panic
+fn fatal(context: &ExceptionContext) -> ! {
+    report(context);
+
+    let (name, kind) = describe(context.vector as u8);
+    let error_code = match kind {
+        ErrorKind::None => u64::MAX,
+        _ => context.error_code,
+    };
+    if let Some(mut registers) = LAST_REGISTERS.try_lock() {
+        *registers = Some(context.registers);
+    }
+    LAST_ERROR_CODE.store(error_code, Ordering::Relaxed);
+    LAST_VECTOR.store(context.vector, Ordering::Relaxed);
+
+    match kind {
+        ErrorKind::None => panic!("EXCEPTION: {}", name),
+        _ => panic!(
+            "EXCEPTION: {}, error code {}",
+            name,
+            ErrorCode(kind, context.error_code)
+        ),
+    }
+}
+
+// 栈溢出时页错误无法在溢出的栈上压入异常帧而升级为双重错误, 此时CR2仍是访问保护页的地址
+// 双重错误之后不会再返回被打断的代码, 其持有的屏幕和串口锁不会再释放, 强制释放后再报告
+fn double_fault(context: &ExceptionContext) -> ! {
+    unsafe {
+        crate::vga_buffer::WARN.force_unlock();
+        crate::serial::SERIAL.force_unlock();
+    }
+    if let Some(name) = memory::vma::guard_page_owner(Cr2::read()) {
+        report!("EXCEPTION: stack overflow in {}", name);
+    }
+    fatal(context);
+}
+
+fn page_fault(context: &ExceptionContext) {
+    let addr = Cr2::read();
+    let error_code = PageFaultErrorCode::from_bits_truncate(context.error_code);
+
+    // 按需映射和写时复制的页在处理后直接返回, 重新执行触发错误的指令
+    if memory::vma::handle_lazy_fault(addr, error_code)
+        || memory::cow::handle_cow_fault(addr, error_code)
+    {
+        return;
+    }
+
+    if let Some(name) = memory::vma::guard_page_owner(addr) {
+        report!("EXCEPTION: stack overflow in {}", name);
+    } else if allocator::heap_guard_hit(addr) {
+        report!("EXCEPTION: heap out of bounds access");
+    }
+
+    report!("Accessed Address: {:?}", addr);
+    memory::dump::page_walk(addr, |step| {
+        report!("  {}", step);
+    });
+    memory::dump::dump_page_tables();
+
+    fatal(context);
+}
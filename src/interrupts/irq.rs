@@ -0,0 +1,224 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use spin::Mutex;
+use x86_64::{
+    instructions::{interrupts, port::Port},
+    structures::idt::{HandlerFunc, InterruptDescriptorTable, InterruptStackFrame},
+};
+
+use super::{apic, PICS, PIC_1_OFFSET};
+
+// 8259和I/O APIC上的ISA中断线数
+pub const IRQ_LINES: usize = 16;
+// 每条中断线最多共享的处理函数个数
+pub const MAX_SHARED_HANDLERS: usize = 4;
+// 8259主片上连接从片的中断线, 不能注册处理函数
+pub const CASCADE_LINE: u8 = 2;
+
+// 8259的命令端口, 写入READ_ISR后可以从中读出中断服务寄存器
+const PIC_1_COMMAND: u16 = 0x20;
+const PIC_2_COMMAND: u16 = 0xa0;
+const READ_ISR: u8 = 0x0b;
+
+// 处理函数的参数为触发的中断线, 共享同一中断线的处理函数都会被调用
+pub type IrqHandler = fn(u8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+    InvalidLine(u8),
+    LineFull(u8),  // 该中断线已有MAX_SHARED_HANDLERS个处理函数
+    NotRegistered, // 处理函数已被注销
+    NotRouted(u8), // 当前的中断控制器无法投递该中断线
+}
+
+// register_irq的返回值, 用于注销对应的处理函数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IrqHandle {
+    line: u8,
+    id: u64,
+}
+
+impl IrqHandle {
+    pub fn line(&self) -> u8 {
+        self.line
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Slot {
+    id: u64,
+    handler: IrqHandler,
+}
+
+type Slots = [Option<Slot>; MAX_SHARED_HANDLERS];
+
+// 使用固定大小的表, 在堆初始化之前也能注册处理函数
+static HANDLERS: Mutex<[Slots; IRQ_LINES]> = Mutex::new([[None; MAX_SHARED_HANDLERS]; IRQ_LINES]);
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU64 = AtomicU64::new(0);
+static IRQ_COUNTS: [AtomicU64; IRQ_LINES] = [ZERO; IRQ_LINES];
+
+// 每条中断线对应一个入口, 在分发前记录中断线号
+macro_rules! irq_stubs {
+    ($($line:literal),*) => {
+        [$({
+            extern "x86-interrupt" fn stub(_stack_frame: InterruptStackFrame) {
+                dispatch($line);
+            }
+            stub as HandlerFunc
+        }),*]
+    };
+}
+
+static STUBS: [HandlerFunc; IRQ_LINES] =
+    irq_stubs!(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15);
+
+// 设置所有中断线的入口, 中断线irq使用向量PIC_1_OFFSET + irq
+pub fn install(idt: &mut InterruptDescriptorTable) {
+    for (line, &stub) in STUBS.iter().enumerate() {
+        idt[usize::from(PIC_1_OFFSET) + line].set_handler_fn(stub);
+    }
+}
+
+// 依次调用中断线上的所有处理函数, 再向中断控制器发送EOI
+fn dispatch(line: u8) {
+    if !apic::is_enabled() && is_spurious(line) {
+        // 从片的伪中断经由主片的级联线投递, 主片仍需要EOI
+        if line == 15 {
+            unsafe {
+                PICS.lock()
+                    .notify_end_of_interrupt(PIC_1_OFFSET + CASCADE_LINE)
+            };
+        }
+        return;
+    }
+
+    IRQ_COUNTS[usize::from(line)].fetch_add(1, Ordering::Relaxed);
+
+    // 复制后释放锁, 处理函数中可以注册或注销处理函数
+    let slots = HANDLERS.lock()[usize::from(line)];
+    for slot in slots.iter().flatten() {
+        (slot.handler)(line);
+    }
+
+    end_of_interrupt(line);
+}
+
+// 中断请求在应答前撤销时, 8259会在优先级最低的中断线(主片7, 从片15)上投递伪中断,
+// 此时中断服务寄存器中对应的位没有置位
+fn is_spurious(line: u8) -> bool {
+    let port = match line {
+        7 => PIC_1_COMMAND,
+        15 => PIC_2_COMMAND,
+        _ => return false,
+    };
+    let _pics = PICS.lock();
+    let mut command: Port<u8> = Port::new(port);
+    let isr = unsafe {
+        command.write(READ_ISR);
+        command.read()
+    };
+    isr & (1 << 7) == 0
+}
+
+fn end_of_interrupt(line: u8) {
+    match apic::local_apic() {
+        Some(local_apic) => local_apic.end_of_interrupt(),
+        None => unsafe { PICS.lock().notify_end_of_interrupt(PIC_1_OFFSET + line) },
+    }
+}
+
+// 在当前使用的中断控制器上屏蔽或打开中断线
+fn set_masked(line: u8, masked: bool) -> Result<(), IrqError> {
+    if apic::is_enabled() {
+        return apic::set_irq_masked(line, masked).map_err(|_| IrqError::NotRouted(line));
+    }
+
+    let mut pics = PICS.lock();
+    let mut masks = unsafe { pics.read_masks() };
+    let (pic, bit) = (usize::from(line / 8), line % 8);
+    match masked {
+        true => masks[pic] |= 1 << bit,
+        false => masks[pic] &= !(1 << bit),
+    }
+    // 从片的中断经由主片的第2条中断线投递
+    if pic == 1 && !masked {
+        masks[0] &= !(1 << 2);
+    }
+    unsafe { pics.write_masks(masks[0], masks[1]) };
+    Ok(())
+}
+
+fn check_line(line: u8) -> Result<(), IrqError> {
+    match usize::from(line) < IRQ_LINES {
+        true => Ok(()),
+        false => Err(IrqError::InvalidLine(line)),
+    }
+}
+
+// 为中断线注册处理函数, 中断线上的第一个处理函数会打开该中断线
+pub fn register_irq(line: u8, handler: IrqHandler) -> Result<IrqHandle, IrqError> {
+    check_line(line)?;
+    if line == CASCADE_LINE {
+        return Err(IrqError::InvalidLine(line));
+    }
+
+    // 关中断后持有锁, 避免与同一中断线的分发死锁
+    interrupts::without_interrupts(|| {
+        let mut handlers = HANDLERS.lock();
+        let slots = &mut handlers[usize::from(line)];
+        let first = slots.iter().all(Option::is_none);
+        let free = slots
+            .iter_mut()
+            .find(|slot| slot.is_none())
+            .ok_or(IrqError::LineFull(line))?;
+
+        if first {
+            set_masked(line, false)?;
+        }
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        *free = Some(Slot { id, handler });
+        Ok(IrqHandle { line, id })
+    })
+}
+
+// 注销处理函数, 中断线上的最后一个处理函数被注销后屏蔽该中断线
+pub fn unregister_irq(handle: IrqHandle) -> Result<(), IrqError> {
+    check_line(handle.line)?;
+
+    interrupts::without_interrupts(|| {
+        let mut handlers = HANDLERS.lock();
+        let slots = &mut handlers[usize::from(handle.line)];
+        let index = slots
+            .iter()
+            .position(|slot| matches!(slot, Some(slot) if slot.id == handle.id))
+            .ok_or(IrqError::NotRegistered)?;
+
+        // 先屏蔽再移除最后一个处理函数, 屏蔽失败时该处理函数仍然有效
+        if slots.iter().flatten().count() == 1 {
+            set_masked(handle.line, true)?;
+        }
+        slots[index] = None;
+        Ok(())
+    })
+}
+
+// 中断线上已注册的处理函数个数
+pub fn handler_count(line: u8) -> usize {
+    match check_line(line) {
+        Ok(()) => interrupts::without_interrupts(|| {
+            HANDLERS.lock()[usize::from(line)].iter().flatten().count()
+        }),
+        Err(_) => 0,
+    }
+}
+
+// 中断线自启动以来触发的次数
+pub fn irq_count(line: u8) -> u64 {
+    match check_line(line) {
+        Ok(()) => IRQ_COUNTS[usize::from(line)].load(Ordering::Relaxed),
+        Err(_) => 0,
+    }
+}
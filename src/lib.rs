@@ -5,14 +5,17 @@
 #![reexport_test_harness_main = "test_main"]
 #![feature(abi_x86_interrupt)]
 #![feature(const_mut_refs)]
+#![feature(alloc_error_handler)]
 
 extern crate alloc;
 
 use core::panic::PanicInfo;
 
-use allocator::{fixed_size_block::FixedSizeBlockAllocator, Locked};
+use allocator::{HeapAllocator, Locked};
 
+pub mod acpi;
 pub mod allocator;
+pub mod backtrace;
 pub mod gdt;
 pub mod interrupts;
 pub mod memory;
@@ -20,13 +23,14 @@ pub mod serial;
 pub mod vga_buffer;
 
 #[global_allocator]
-// 使用自定义的FixedSizeBlock分配器
-static ALLOCATOR: Locked<FixedSizeBlockAllocator> = Locked::new(FixedSizeBlockAllocator::new());
+// 使用由cargo特性选择的分配器
+static ALLOCATOR: Locked<HeapAllocator> = Locked::new(HeapAllocator::new());
 
 pub fn init() {
     gdt::init();
     interrupts::init_idt();
     unsafe { interrupts::PICS.lock().initialize() };
+    interrupts::init_irqs();
     x86_64::instructions::interrupts::enable();
 }
 
@@ -56,6 +60,7 @@ pub fn test_runner(tests: &[&dyn Testable]) {
 pub fn test_panic_handler(info: &PanicInfo) -> ! {
     serial_println!("[failed]\n");
     serial_println!("Error: {}\n", info);
+    serial_println!("backtrace:\n{}", backtrace::Backtrace::capture());
     exit_qemu(QemuExitCode::Failed);
     hlt_loop();
 }
@@ -14,13 +14,16 @@ use x86_64::{structures::paging::Page, VirtAddr};
 
 use rust_os::{
     allocator,
-    memory::{self, BootInfoFrameAllocator},
+    backtrace::Backtrace,
+    interrupts,
+    memory::{self, frame_allocator::FreeListFrameAllocator},
     println,
 };
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     println!("{}", info);
+    println!("backtrace:\n{}", Backtrace::capture());
     rust_os::hlt_loop();
 }
 
@@ -37,13 +40,23 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
 
     let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
     let mut mapper = unsafe { memory::init(phys_mem_offset) };
-    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    let mut frame_allocator =
+        unsafe { FreeListFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
 
     allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization faild");
+    memory::protection::protect_kernel_sections(&mut mapper)
+        .expect("kernel section protection failed");
 
     let page = Page::containing_address(VirtAddr::new(0));
     memory::create_example_mapping(page, &mut mapper, &mut frame_allocator);
 
+    // 之后的堆扩展通过全局的页表映射器与帧分配器完成
+    memory::init_kernel_memory(mapper, frame_allocator);
+    memory::report::print_memory_map(&boot_info.memory_map);
+    if let Err(err) = interrupts::apic::init() {
+        println!("APIC unavailable, using 8259 PIC: {:?}", err);
+    }
+
     let page_ptr: *mut u64 = page.start_address().as_mut_ptr();
     unsafe { page_ptr.offset(400).write_volatile(0x_f021_f077_f065_f04e) };
 
@@ -1,19 +1,81 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
 use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use spin::{Mutex, MutexGuard};
 use x86_64::{
     registers::control::Cr3,
     structures::paging::{
-        page_table::FrameError, FrameAllocator, Mapper, OffsetPageTable, Page, PageTable,
-        PhysFrame, Size4KiB,
+        mapper::MapToError,
+        page_table::{FrameError, PageTableEntry},
+        FrameAllocator, Mapper, OffsetPageTable, Page, PageSize, PageTable, PageTableFlags,
+        PhysFrame, Size1GiB, Size2MiB, Size4KiB,
     },
     PhysAddr, VirtAddr,
 };
 
+use frame_allocator::FreeListFrameAllocator;
+
+use crate::allocator;
+
+pub use mmio::{map_mmio, MmioRegion};
+
+pub mod address_space;
+pub mod buddy;
+pub mod cow;
+pub mod dump;
+pub mod frame_allocator;
+pub mod mmio;
+pub mod protection;
+pub mod report;
+pub mod vma;
+
+// 内核全局的页表映射器与物理帧分配器, 供堆扩展等运行时需要映射内存的地方使用
+pub struct KernelMemory {
+    pub mapper: OffsetPageTable<'static>,
+    pub frame_allocator: FreeListFrameAllocator,
+}
+
+pub static KERNEL_MEMORY: Mutex<Option<KernelMemory>> = Mutex::new(None);
+
+// 获取KERNEL_MEMORY, 在此之前先为堆预留HEAP_RESERVE的空闲空间
+// 持有该锁时堆无法扩展, 除页错误处理等不能分配内存的路径外都应通过该函数获取
+pub fn lock_kernel_memory() -> MutexGuard<'static, Option<KernelMemory>> {
+    allocator::reserve_heap(allocator::HEAP_RESERVE);
+    KERNEL_MEMORY.lock()
+}
+
+// 物理内存映射的起始虚拟地址, 在init中记录, 0表示尚未初始化
+static PHYSICAL_MEMORY_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+pub fn physical_memory_offset() -> Option<VirtAddr> {
+    match PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed) {
+        0 => None,
+        offset => Some(VirtAddr::new(offset)),
+    }
+}
+
 // 初始化
 pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+    // 之后的映射会使用NO_EXECUTE, 需要先启用
+    protection::enable_nx_and_write_protect();
+    PHYSICAL_MEMORY_OFFSET.store(physical_memory_offset.as_u64(), Ordering::Relaxed);
     let level_4_table = active_level_4_table(physical_memory_offset);
     OffsetPageTable::new(level_4_table, physical_memory_offset)
 }
 
+// 将页表映射器与帧分配器交由内核全局管理
+pub fn init_kernel_memory(
+    mapper: OffsetPageTable<'static>,
+    frame_allocator: FreeListFrameAllocator,
+) {
+    *KERNEL_MEMORY.lock() = Some(KernelMemory {
+        mapper,
+        frame_allocator,
+    });
+    // 中断栈的保护页需要通过KERNEL_MEMORY取消映射
+    crate::gdt::protect_stacks().expect("stack protection failed");
+}
+
 unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut PageTable {
     let (level_4_table_frame, _) = Cr3::read();
 
@@ -39,7 +101,7 @@ fn _translate_addr(addr: VirtAddr, physical_memory_offset: VirtAddr) -> Option<P
     ];
     let mut frame = level_4_table_frame; 
 
-    for &index in &table_index {
+    for (level, &index) in table_index.iter().enumerate() {
         let virt = physical_memory_offset + frame.start_address().as_u64();
         let table_ptr: *const PageTable = virt.as_ptr();
         let table = unsafe { &*table_ptr };
@@ -48,13 +110,107 @@ fn _translate_addr(addr: VirtAddr, physical_memory_offset: VirtAddr) -> Option<P
         frame = match entry.frame() {
             Ok(frame)             => frame,
             Err(FrameError::FrameNotPresent) => return None,
-            Err(FrameError::HugeFrame)       => panic!("huge pages not supported"),
+            Err(FrameError::HugeFrame)       => {
+                // P3中的大页为1GiB, P2中的大页为2MiB, P4中不允许出现大页
+                let page_size = match level {
+                    1 => Size1GiB::SIZE,
+                    2 => Size2MiB::SIZE,
+                    _ => return None,
+                };
+                let frame_addr = huge_frame_addr(entry, page_size);
+                return Some(frame_addr + (addr.as_u64() & (page_size - 1)));
+            }
         };
     }
 
     Some(frame.start_address() + u64::from(addr.page_offset()))
 }
 
+// 大页项映射的物理帧起始地址
+// 大页项的第12位是PAT位, 不属于物理地址, 需按页大小向下对齐去掉
+pub(crate) fn huge_frame_addr(entry: &PageTableEntry, page_size: u64) -> PhysAddr {
+    entry.addr().align_down(page_size)
+}
+
+// 检查CPU是否支持1GiB大页
+pub fn supports_1gib_pages() -> bool {
+    use core::arch::x86_64::__cpuid;
+
+    let max_extended_leaf = unsafe { __cpuid(0x8000_0000) }.eax;
+    if max_extended_leaf < 0x8000_0001 {
+        return false;
+    }
+    unsafe { __cpuid(0x8000_0001) }.edx & (1 << 26) != 0
+}
+
+// 软件定义的标志, 使用页表项中CPU忽略的第10位
+// 标记map_physical_range建立的映射, 其物理帧不属于映射所在的地址空间, 释放地址空间时不回收
+pub const PHYSICAL_RANGE: PageTableFlags = PageTableFlags::BIT_10;
+
+// 带有这些标志的P1项映射的物理帧是设备内存或由其他地方拥有, 不能共享, 也不随映射一起释放
+pub(crate) const BORROWED_FLAGS: PageTableFlags = PageTableFlags::NO_CACHE.union(PHYSICAL_RANGE);
+
+// 将一段物理地址连续映射到虚拟地址, 在地址对齐且剩余大小足够时优先使用1GiB和2MiB大页
+// virt, phys和size需按4KiB对齐, 所有映射都会带上PHYSICAL_RANGE标志
+pub unsafe fn map_physical_range(
+    virt: VirtAddr,
+    phys: PhysAddr,
+    size: u64,
+    flags: PageTableFlags,
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
+    let use_1gib = supports_1gib_pages();
+    let flags = flags | PHYSICAL_RANGE;
+    let mut offset = 0;
+
+    while offset < size {
+        let (virt, phys, remaining) = (virt + offset, phys + offset, size - offset);
+        let fits = |page_size: u64| {
+            virt.is_aligned(page_size) && phys.is_aligned(page_size) && remaining >= page_size
+        };
+
+        let page_size = if use_1gib && fits(Size1GiB::SIZE) {
+            map_sized_page::<Size1GiB>(virt, phys, flags, mapper, frame_allocator)?
+        } else if fits(Size2MiB::SIZE) {
+            map_sized_page::<Size2MiB>(virt, phys, flags, mapper, frame_allocator)?
+        } else {
+            map_sized_page::<Size4KiB>(virt, phys, flags, mapper, frame_allocator)?
+        };
+        offset += page_size;
+    }
+
+    Ok(())
+}
+
+// 以S大小的页映射virt到phys, 返回映射的大小
+// 大页的错误统一转换为4KiB页的错误
+pub unsafe fn map_sized_page<S: PageSize>(
+    virt: VirtAddr,
+    phys: PhysAddr,
+    flags: PageTableFlags,
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<u64, MapToError<Size4KiB>>
+where
+    for<'a> OffsetPageTable<'a>: Mapper<S>,
+{
+    let page = Page::<S>::containing_address(virt);
+    let frame = PhysFrame::<S>::containing_address(phys);
+
+    match mapper.map_to(page, frame, flags, frame_allocator) {
+        Ok(flush) => {
+            flush.flush();
+            Ok(S::SIZE)
+        }
+        Err(MapToError::FrameAllocationFailed) => Err(MapToError::FrameAllocationFailed),
+        Err(MapToError::ParentEntryHugePage) => Err(MapToError::ParentEntryHugePage),
+        Err(MapToError::PageAlreadyMapped(frame)) => Err(MapToError::PageAlreadyMapped(
+            PhysFrame::containing_address(frame.start_address()),
+        )),
+    }
+}
+
 pub fn create_example_mapping(
     page: Page,
     mapper: &mut OffsetPageTable,
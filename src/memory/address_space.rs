@@ -0,0 +1,240 @@
+use x86_64::{
+    registers::control::Cr3,
+    structures::paging::{
+        FrameAllocator, FrameDeallocator, OffsetPageTable, PageTable, PageTableFlags,
+        PageTableIndex, PhysFrame,
+    },
+    PhysAddr, VirtAddr,
+};
+
+use super::{
+    frame_allocator::FreeListFrameAllocator, lock_kernel_memory, vma, KernelMemory, BORROWED_FLAGS,
+};
+
+// 低半部分中未被内核使用的P4项属于各地址空间自己, 高半部分总是与内核共享
+pub const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
+const KERNEL_HALF_START: usize = 256;
+
+#[derive(Debug)]
+pub enum AddressSpaceError {
+    NotInitialized, // memory::KERNEL_MEMORY尚未初始化
+    FrameAllocationFailed,
+    SharedRange, // 范围超出低半部分, 或位于与内核共享的P4项内
+}
+
+// 一个独立的地址空间, 拥有自己的P4页表
+// 内核使用的P4项直接复制自内核页表, 因此内核映射在所有地址空间中可见,
+// 其余低半部分的页表以及映射的物理帧在drop时释放, 设备内存和map_physical_range的映射除外
+// 内核映像, 堆和物理内存映射都位于低半部分, 它们所在的P4项同样共享, 不能在其中建立私有映射
+// 地址空间私有的P4项不会再被内核的新映射覆盖, 内核之后在这些P4项内的映射对该地址空间不可见
+pub struct AddressSpace {
+    level_4_frame: PhysFrame,
+}
+
+impl AddressSpace {
+    // 创建一个只包含内核映射的地址空间
+    pub fn new() -> Result<Self, AddressSpaceError> {
+        let mut kernel_memory = lock_kernel_memory();
+        let memory = kernel_memory
+            .as_mut()
+            .ok_or(AddressSpaceError::NotInitialized)?;
+
+        // 先为内核虚拟地址范围分配好P3页表, 之后内核新建的映射才能被所有地址空间看到
+        prepare_kernel_half(memory)?;
+
+        let frame = memory
+            .frame_allocator
+            .allocate_frame()
+            .ok_or(AddressSpaceError::FrameAllocationFailed)?;
+        let phys_offset = memory.mapper.phys_offset();
+        let table = unsafe { table_at(phys_offset, frame) };
+        table.zero();
+        sync_kernel_entries(memory.mapper.level_4_table(), table);
+
+        Ok(AddressSpace {
+            level_4_frame: frame,
+        })
+    }
+
+    pub fn level_4_frame(&self) -> PhysFrame {
+        self.level_4_frame
+    }
+
+    pub fn is_active(&self) -> bool {
+        Cr3::read().0 == self.level_4_frame
+    }
+
+    // 以该地址空间的页表调用f, 用于在[start, start + size)内建立或取消映射
+    // 该范围必须位于低半部分中未与内核共享的P4项内, 否则映射会修改内核自己的页表
+    pub fn with_mapper<R>(
+        &mut self,
+        start: VirtAddr,
+        size: u64,
+        f: impl FnOnce(&mut OffsetPageTable, &mut FreeListFrameAllocator) -> R,
+    ) -> Result<R, AddressSpaceError> {
+        let mut kernel_memory = lock_kernel_memory();
+        let memory = kernel_memory
+            .as_mut()
+            .ok_or(AddressSpaceError::NotInitialized)?;
+
+        let end = start
+            .as_u64()
+            .checked_add(size)
+            .filter(|&end| size > 0 && end <= USER_SPACE_END)
+            .ok_or(AddressSpaceError::SharedRange)?;
+        let phys_offset = memory.mapper.phys_offset();
+        let kernel_table = memory.mapper.level_4_table();
+        let table = unsafe { table_at(phys_offset, self.level_4_frame) };
+        let first = usize::from(start.p4_index());
+        let last = usize::from(VirtAddr::new(end - 1).p4_index());
+        // 内核在创建之后新增的P4项尚未同步时同样视为共享
+        if (first..=last).any(|index| {
+            let kernel_entry = &kernel_table[index];
+            !kernel_entry.is_unused()
+                && (table[index].is_unused() || table[index].addr() == kernel_entry.addr())
+        }) {
+            return Err(AddressSpaceError::SharedRange);
+        }
+
+        let mut mapper = unsafe { OffsetPageTable::new(table, phys_offset) };
+        Ok(f(&mut mapper, &mut memory.frame_allocator))
+    }
+
+    // 切换到该地址空间
+    // 切换前会同步内核在创建之后新增的P4项
+    pub unsafe fn switch_to(&self) -> Result<(), AddressSpaceError> {
+        let mut kernel_memory = lock_kernel_memory();
+        let memory = kernel_memory
+            .as_mut()
+            .ok_or(AddressSpaceError::NotInitialized)?;
+
+        let table = table_at(memory.mapper.phys_offset(), self.level_4_frame);
+        sync_kernel_entries(memory.mapper.level_4_table(), table);
+
+        let (_, flags) = Cr3::read();
+        Cr3::write(self.level_4_frame, flags);
+        Ok(())
+    }
+}
+
+// 切换回内核页表
+pub unsafe fn switch_to_kernel() -> Result<(), AddressSpaceError> {
+    let mut kernel_memory = lock_kernel_memory();
+    let memory = kernel_memory
+        .as_mut()
+        .ok_or(AddressSpaceError::NotInitialized)?;
+
+    let (_, flags) = Cr3::read();
+    Cr3::write(kernel_level_4_frame(memory), flags);
+    Ok(())
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        let mut kernel_memory = lock_kernel_memory();
+        let memory = match kernel_memory.as_mut() {
+            Some(memory) => memory,
+            None => return,
+        };
+
+        // 不能释放正在使用的页表
+        if self.is_active() {
+            let (_, flags) = Cr3::read();
+            unsafe { Cr3::write(kernel_level_4_frame(memory), flags) };
+        }
+
+        let phys_offset = memory.mapper.phys_offset();
+        let kernel_table = memory.mapper.level_4_table();
+        let table = unsafe { table_at(phys_offset, self.level_4_frame) };
+        let frame_allocator = &mut memory.frame_allocator;
+
+        for index in 0..KERNEL_HALF_START {
+            let entry = &table[index];
+            let shared =
+                !kernel_table[index].is_unused() && kernel_table[index].addr() == entry.addr();
+            if entry.is_unused() || shared {
+                continue;
+            }
+            if let Ok(frame) = entry.frame() {
+                unsafe { free_table(phys_offset, frame, 3, frame_allocator) };
+            }
+        }
+
+        unsafe { frame_allocator.deallocate_frame(self.level_4_frame) };
+    }
+}
+
+fn kernel_level_4_frame(memory: &mut KernelMemory) -> PhysFrame {
+    let phys_offset = memory.mapper.phys_offset();
+    let virt = VirtAddr::from_ptr(memory.mapper.level_4_table() as *const PageTable);
+    PhysFrame::containing_address(PhysAddr::new(virt - phys_offset))
+}
+
+unsafe fn table_at(phys_offset: VirtAddr, frame: PhysFrame) -> &'static mut PageTable {
+    let virt = phys_offset + frame.start_address().as_u64();
+    &mut *virt.as_mut_ptr()
+}
+
+// 为内核虚拟地址范围内尚不存在的P4项分配空的P3页表
+fn prepare_kernel_half(memory: &mut KernelMemory) -> Result<(), AddressSpaceError> {
+    let phys_offset = memory.mapper.phys_offset();
+    let start = VirtAddr::new(vma::KERNEL_VMA_START).p4_index();
+    let end = VirtAddr::new(vma::KERNEL_VMA_END - 1).p4_index();
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+    for index in u16::from(start)..=u16::from(end) {
+        let index = PageTableIndex::new(index);
+        if !memory.mapper.level_4_table()[index].is_unused() {
+            continue;
+        }
+
+        let frame = memory
+            .frame_allocator
+            .allocate_frame()
+            .ok_or(AddressSpaceError::FrameAllocationFailed)?;
+        unsafe { table_at(phys_offset, frame) }.zero();
+        memory.mapper.level_4_table()[index].set_frame(frame, flags);
+    }
+
+    Ok(())
+}
+
+// 将内核页表中存在的P4项复制到table, 不覆盖table中已有的项
+fn sync_kernel_entries(kernel_table: &PageTable, table: &mut PageTable) {
+    for (entry, kernel_entry) in table.iter_mut().zip(kernel_table.iter()) {
+        if entry.is_unused() && !kernel_entry.is_unused() {
+            *entry = kernel_entry.clone();
+        }
+    }
+}
+
+// 释放level级页表及其下所有页表和映射的4KiB物理帧
+// 大页映射的物理帧不由帧分配器按单帧管理, 不予释放
+// 不可缓存的设备内存以及map_physical_range映射的物理帧不属于地址空间, 同样不释放
+unsafe fn free_table(
+    phys_offset: VirtAddr,
+    frame: PhysFrame,
+    level: u8,
+    frame_allocator: &mut FreeListFrameAllocator,
+) {
+    let table = table_at(phys_offset, frame);
+    for entry in table.iter() {
+        // P1中的第7位是PAT而不是HUGE_PAGE
+        let huge = level > 1 && entry.flags().contains(PageTableFlags::HUGE_PAGE);
+        if entry.is_unused() || huge {
+            continue;
+        }
+        if let Ok(next) = entry.frame() {
+            if level == 1 {
+                if !entry.flags().intersects(BORROWED_FLAGS) {
+                    frame_allocator.deallocate_frame(next);
+                }
+            } else {
+                free_table(phys_offset, next, level - 1, frame_allocator);
+            }
+        }
+    }
+
+    frame_allocator.deallocate_frame(frame);
+}
@@ -0,0 +1,199 @@
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use x86_64::{
+    structures::paging::{
+        FrameAllocator, FrameDeallocator, PageSize, PhysFrame, Size2MiB, Size4KiB,
+    },
+    PhysAddr, VirtAddr,
+};
+
+use super::frame_allocator::FrameStats;
+
+const FRAME_SIZE: u64 = 4096;
+
+// 最大的阶, 对应 4KiB << 10 = 4MiB 的块
+pub const MAX_ORDER: usize = 10;
+
+// 2MiB大页对应的阶
+pub const HUGE_FRAME_ORDER: usize = 9;
+
+// 伙伴系统物理内存分配器
+// 第n阶的块包含 2^n 个连续的4KiB帧, 且起始地址按块大小对齐,
+// 空闲块组成按阶划分的链表, 链表节点存放在空闲块的起始位置
+pub struct BuddyFrameAllocator {
+    free_lists: [Option<PhysAddr>; MAX_ORDER + 1],
+    physical_memory_offset: VirtAddr,
+    total_frames: usize,
+    used_frames: usize,
+}
+
+// 返回指定阶的块大小(字节)
+pub const fn block_size(order: usize) -> u64 {
+    FRAME_SIZE << order
+}
+
+// 返回能容纳size字节的最小阶
+pub fn order_for_size(size: u64) -> Option<usize> {
+    (0..=MAX_ORDER).find(|&order| block_size(order) >= size)
+}
+
+impl BuddyFrameAllocator {
+    // 将内存映射中所有Usable区域切分为尽可能大的对齐块
+    // 调用者需保证这些区域确实未被使用, 且整个物理内存已被映射到physical_memory_offset处
+    pub unsafe fn init(memory_map: &'static MemoryMap, physical_memory_offset: VirtAddr) -> Self {
+        const EMPTY: Option<PhysAddr> = None;
+        let mut allocator = BuddyFrameAllocator {
+            free_lists: [EMPTY; MAX_ORDER + 1],
+            physical_memory_offset,
+            total_frames: 0,
+            used_frames: 0,
+        };
+
+        let usable_regions = memory_map
+            .iter()
+            .filter(|r| r.region_type == MemoryRegionType::Usable);
+        for region in usable_regions {
+            allocator.add_region(region.range.start_addr(), region.range.end_addr());
+        }
+
+        allocator
+    }
+
+    unsafe fn add_region(&mut self, start: u64, end: u64) {
+        let mut addr = start;
+        while addr + FRAME_SIZE <= end {
+            // 选取地址对齐且不超出区域的最大阶
+            let order = (0..=MAX_ORDER)
+                .rev()
+                .find(|&order| addr % block_size(order) == 0 && addr + block_size(order) <= end)
+                .unwrap();
+
+            self.push(PhysAddr::new(addr), order);
+            self.total_frames += 1 << order;
+            addr += block_size(order);
+        }
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        FrameStats {
+            total_frames: self.total_frames,
+            used_frames: self.used_frames,
+            free_frames: self.total_frames - self.used_frames,
+        }
+    }
+
+    // 返回指定阶的空闲块数量
+    pub fn free_blocks(&self, order: usize) -> usize {
+        let mut count = 0;
+        let mut current = self.free_lists[order];
+        while let Some(addr) = current {
+            count += 1;
+            current = unsafe { self.node(addr).read() };
+        }
+        count
+    }
+
+    // 分配一个第order阶的块, 返回其起始物理地址
+    pub fn allocate(&mut self, order: usize) -> Option<PhysAddr> {
+        if order > MAX_ORDER {
+            return None;
+        }
+
+        // 找到不小于order的最小非空阶
+        let mut current = (order..=MAX_ORDER).find(|&o| self.free_lists[o].is_some())?;
+        let addr = unsafe { self.pop(current) }.unwrap();
+
+        // 将多余的部分逐级拆分, 把后半部分放回空闲链表
+        while current > order {
+            current -= 1;
+            unsafe { self.push(addr + block_size(current), current) };
+        }
+
+        self.used_frames += 1 << order;
+        Some(addr)
+    }
+
+    // 释放一个第order阶的块, 并与空闲的伙伴块合并
+    // 调用者需保证该块由allocate以相同的order分配
+    pub unsafe fn deallocate(&mut self, addr: PhysAddr, order: usize) {
+        assert!(addr.is_aligned(block_size(order)));
+
+        self.used_frames -= 1 << order;
+
+        let mut addr = addr;
+        let mut order = order;
+        while order < MAX_ORDER {
+            let buddy = PhysAddr::new(addr.as_u64() ^ block_size(order));
+            if !self.remove(buddy, order) {
+                break;
+            }
+            addr = PhysAddr::new(addr.as_u64() & !block_size(order));
+            order += 1;
+        }
+
+        self.push(addr, order);
+    }
+
+    // 获取存放在空闲块中的链表节点
+    fn node(&self, addr: PhysAddr) -> *mut Option<PhysAddr> {
+        let virt = self.physical_memory_offset + addr.as_u64();
+        virt.as_mut_ptr()
+    }
+
+    unsafe fn push(&mut self, addr: PhysAddr, order: usize) {
+        self.node(addr).write(self.free_lists[order]);
+        self.free_lists[order] = Some(addr);
+    }
+
+    unsafe fn pop(&mut self, order: usize) -> Option<PhysAddr> {
+        let addr = self.free_lists[order]?;
+        self.free_lists[order] = self.node(addr).read();
+        Some(addr)
+    }
+
+    // 从第order阶的空闲链表中移除指定的块, 若该块不在链表中则返回false
+    unsafe fn remove(&mut self, target: PhysAddr, order: usize) -> bool {
+        let mut prev: Option<PhysAddr> = None;
+        let mut current = self.free_lists[order];
+
+        while let Some(addr) = current {
+            let next = self.node(addr).read();
+            if addr == target {
+                match prev {
+                    Some(prev) => self.node(prev).write(next),
+                    None => self.free_lists[order] = next,
+                }
+                return true;
+            }
+            prev = Some(addr);
+            current = next;
+        }
+
+        false
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BuddyFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        self.allocate(0).map(PhysFrame::containing_address)
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BuddyFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
+        self.deallocate(frame.start_address(), 0);
+    }
+}
+
+unsafe impl FrameAllocator<Size2MiB> for BuddyFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+        debug_assert_eq!(block_size(HUGE_FRAME_ORDER), Size2MiB::SIZE);
+        self.allocate(HUGE_FRAME_ORDER)
+            .map(PhysFrame::containing_address)
+    }
+}
+
+impl FrameDeallocator<Size2MiB> for BuddyFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size2MiB>) {
+        self.deallocate(frame.start_address(), HUGE_FRAME_ORDER);
+    }
+}
@@ -0,0 +1,144 @@
+use x86_64::{
+    structures::idt::PageFaultErrorCode,
+    structures::paging::{
+        mapper::{FlagUpdateError, MapToError, MappedFrame, TranslateResult},
+        FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageSize, PageTableFlags,
+        Size4KiB, Translate,
+    },
+    VirtAddr,
+};
+
+use super::{frame_allocator::FreeListFrameAllocator, BORROWED_FLAGS, KERNEL_MEMORY};
+
+// 软件定义的写时复制标志, 使用页表项中CPU忽略的第9位
+pub const COW: PageTableFlags = PageTableFlags::BIT_9;
+
+// 这些位由CPU维护, 重新映射时不保留
+const CPU_FLAGS: PageTableFlags = PageTableFlags::ACCESSED.union(PageTableFlags::DIRTY);
+
+#[derive(Debug)]
+pub enum CowError {
+    NotMapped,
+    HugePage,     // 只支持共享4KiB页
+    NotShareable, // 设备内存, map_physical_range的映射等不由帧分配器管理的帧
+    Map(MapToError<Size4KiB>),
+    FlagUpdate(FlagUpdateError),
+}
+
+// 将src所映射的物理帧同时映射到dst, 两者共享同一帧
+// 可写的页在两处都改为只读并设置COW标志, 之后的写入会触发页错误并复制该帧
+pub fn share_page(
+    src: Page,
+    dst: Page,
+    mapper: &mut OffsetPageTable,
+    frame_allocator: &mut FreeListFrameAllocator,
+) -> Result<(), CowError> {
+    let (frame, flags) = match mapper.translate(src.start_address()) {
+        TranslateResult::Mapped {
+            frame: MappedFrame::Size4KiB(frame),
+            flags,
+            ..
+        } => (frame, flags - CPU_FLAGS),
+        TranslateResult::Mapped { .. } => return Err(CowError::HugePage),
+        _ => return Err(CowError::NotMapped),
+    };
+    if flags.intersects(BORROWED_FLAGS) || !frame_allocator.manages(frame) {
+        return Err(CowError::NotShareable);
+    }
+
+    let flags = if flags.contains(PageTableFlags::WRITABLE) {
+        let flags = (flags - PageTableFlags::WRITABLE) | COW;
+        unsafe {
+            mapper
+                .update_flags(src, flags)
+                .map_err(CowError::FlagUpdate)?
+                .flush()
+        };
+        flags
+    } else {
+        flags
+    };
+
+    unsafe {
+        mapper
+            .map_to(dst, frame, flags, frame_allocator)
+            .map_err(CowError::Map)?
+            .flush()
+    };
+    frame_allocator.share_frame(frame);
+
+    Ok(())
+}
+
+// 处理对COW页的写入: 帧仍被共享时复制一份并映射为可写, 否则直接恢复写权限
+// 返回true表示错误已解决, 可以重新执行触发错误的指令
+// 在页错误处理中调用, 锁被占用时放弃处理
+pub fn handle_cow_fault(addr: VirtAddr, error_code: PageFaultErrorCode) -> bool {
+    let write_to_present = PageFaultErrorCode::PROTECTION_VIOLATION
+        | PageFaultErrorCode::CAUSED_BY_WRITE;
+    if !error_code.contains(write_to_present) {
+        return false;
+    }
+
+    let mut kernel_memory = match KERNEL_MEMORY.try_lock() {
+        Some(kernel_memory) => kernel_memory,
+        None => return false,
+    };
+    let memory = match kernel_memory.as_mut() {
+        Some(memory) => memory,
+        None => return false,
+    };
+    let (mapper, frame_allocator) = (&mut memory.mapper, &mut memory.frame_allocator);
+
+    let page = Page::containing_address(addr);
+    let (frame, flags) = match mapper.translate(page.start_address()) {
+        TranslateResult::Mapped {
+            frame: MappedFrame::Size4KiB(frame),
+            flags,
+            ..
+        } if flags.contains(COW) => (frame, flags - CPU_FLAGS),
+        _ => return false,
+    };
+    let flags = (flags - COW) | PageTableFlags::WRITABLE;
+
+    // 最后一个引用者无需复制
+    if frame_allocator.ref_count(frame) == 1 {
+        return match unsafe { mapper.update_flags(page, flags) } {
+            Ok(flush) => {
+                flush.flush();
+                true
+            }
+            Err(_) => false,
+        };
+    }
+
+    let copy = match frame_allocator.allocate_frame() {
+        Some(frame) => frame,
+        None => return false,
+    };
+    let phys_offset = mapper.phys_offset();
+    unsafe {
+        let src: *const u8 = (phys_offset + frame.start_address().as_u64()).as_ptr();
+        let dst: *mut u8 = (phys_offset + copy.start_address().as_u64()).as_mut_ptr();
+        dst.copy_from_nonoverlapping(src, Size4KiB::SIZE as usize);
+    }
+
+    match mapper.unmap(page) {
+        Ok((_, flush)) => flush.flush(),
+        Err(_) => {
+            unsafe { frame_allocator.deallocate_frame(copy) };
+            return false;
+        }
+    }
+    match unsafe { mapper.map_to(page, copy, flags, frame_allocator) } {
+        Ok(flush) => flush.flush(),
+        Err(_) => {
+            unsafe { frame_allocator.deallocate_frame(copy) };
+            return false;
+        }
+    }
+
+    // 释放对原帧的引用
+    unsafe { frame_allocator.deallocate_frame(frame) };
+    true
+}
@@ -0,0 +1,227 @@
+use core::fmt;
+
+use x86_64::{
+    registers::control::Cr3,
+    structures::paging::{PageTable, PageTableFlags},
+    PhysAddr, VirtAddr,
+};
+
+use crate::serial_println;
+
+// 合并后的一段连续映射
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MappedRange {
+    pub virt_start: VirtAddr,
+    pub phys_start: PhysAddr,
+    pub size: u64,
+    pub page_size: u64,
+    pub flags: PageTableFlags, // 综合各级页表项后的有效权限
+}
+
+impl MappedRange {
+    pub fn virt_end(&self) -> VirtAddr {
+        self.virt_start + self.size
+    }
+
+    pub fn phys_end(&self) -> PhysAddr {
+        self.phys_start + self.size
+    }
+
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        self.virt_start <= addr && addr < self.virt_end()
+    }
+
+    // 能否将next合并到当前范围的末尾
+    fn can_merge(&self, next: &MappedRange) -> bool {
+        self.virt_end() == next.virt_start
+            && self.phys_end() == next.phys_start
+            && self.page_size == next.page_size
+            && self.flags == next.flags
+    }
+}
+
+// 以紧凑的形式显示页表标志: 读写, 用户/内核, 可执行, 全局, 缓存策略
+pub struct CompactFlags(pub PageTableFlags);
+
+impl fmt::Display for CompactFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flags = self.0;
+        let flag = |flag, set, unset| if flags.contains(flag) { set } else { unset };
+
+        write!(
+            f,
+            "r{}{}{}{}{}",
+            flag(PageTableFlags::WRITABLE, 'w', '-'),
+            if flags.contains(PageTableFlags::NO_EXECUTE) { '-' } else { 'x' },
+            flag(PageTableFlags::USER_ACCESSIBLE, 'u', 'k'),
+            flag(PageTableFlags::GLOBAL, 'g', '-'),
+            flag(PageTableFlags::NO_CACHE, 'c', flag(PageTableFlags::WRITE_THROUGH, 't', '-')),
+        )
+    }
+}
+
+// 需要每一级页表都允许才生效的权限
+const ALL_LEVEL_FLAGS: PageTableFlags =
+    PageTableFlags::WRITABLE.union(PageTableFlags::USER_ACCESSIBLE);
+
+// 这些位由CPU维护, 合并时忽略
+const IGNORED_FLAGS: PageTableFlags = PageTableFlags::ACCESSED.union(PageTableFlags::DIRTY);
+
+// 遍历当前活动的页表, 按地址顺序对每段合并后的映射调用f
+// 需要先调用memory::init, 否则无法访问页表
+pub fn for_each_mapping(mut f: impl FnMut(&MappedRange)) {
+    let physical_memory_offset = match super::physical_memory_offset() {
+        Some(offset) => offset,
+        None => return,
+    };
+    let (level_4_table_frame, _) = Cr3::read();
+
+    let mut current: Option<MappedRange> = None;
+    let mut merge = |range: MappedRange| match current.as_mut() {
+        Some(current) if current.can_merge(&range) => current.size += range.size,
+        _ => {
+            if let Some(previous) = current.replace(range) {
+                f(&previous);
+            }
+        }
+    };
+
+    let table = unsafe { table_at(physical_memory_offset, level_4_table_frame.start_address()) };
+    // 禁止执行只要任一级设置即生效
+    walk(table, 4, 0, ALL_LEVEL_FLAGS, physical_memory_offset, &mut merge);
+
+    if let Some(last) = current {
+        f(&last);
+    }
+}
+
+// 将所有映射输出到串口
+pub fn dump_page_tables() {
+    serial_println!("page table mappings:");
+    for_each_mapping(|range| {
+        serial_println!(
+            "  {:#018x}-{:#018x} -> {:#012x}-{:#012x} {:>4} {}",
+            range.virt_start.as_u64(),
+            range.virt_end().as_u64(),
+            range.phys_start.as_u64(),
+            range.phys_end().as_u64(),
+            page_size_name(range.page_size),
+            CompactFlags(range.flags),
+        );
+    });
+}
+
+// 页表查找中某一级的页表项
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalkStep {
+    pub level: u8,
+    pub index: u16,
+    pub addr: PhysAddr, // 页表项中的物理地址
+    pub flags: PageTableFlags,
+}
+
+impl fmt::Display for WalkStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.flags.contains(PageTableFlags::PRESENT) {
+            return write!(f, "P{}[{:3}] not present", self.level, self.index);
+        }
+        write!(
+            f,
+            "P{}[{:3}] -> {:#012x} {}{}",
+            self.level,
+            self.index,
+            self.addr.as_u64(),
+            CompactFlags(self.flags),
+            if self.level > 1 && self.flags.contains(PageTableFlags::HUGE_PAGE) {
+                " huge"
+            } else {
+                ""
+            },
+        )
+    }
+}
+
+// 在当前活动的页表中逐级查找addr, 对每一级的页表项调用f
+// 遇到不存在的项, 大页或P1项时停止
+pub fn page_walk(addr: VirtAddr, mut f: impl FnMut(&WalkStep)) {
+    let physical_memory_offset = match super::physical_memory_offset() {
+        Some(offset) => offset,
+        None => return,
+    };
+    let indexes = [
+        addr.p4_index(),
+        addr.p3_index(),
+        addr.p2_index(),
+        addr.p1_index(),
+    ];
+
+    let mut table_addr = Cr3::read().0.start_address();
+    for (level, &index) in (1..=4u8).rev().zip(indexes.iter()) {
+        let table = unsafe { table_at(physical_memory_offset, table_addr) };
+        let entry = &table[index];
+        let step = WalkStep {
+            level,
+            index: u16::from(index),
+            addr: entry.addr(),
+            flags: entry.flags(),
+        };
+        f(&step);
+
+        let present = step.flags.contains(PageTableFlags::PRESENT);
+        if !present || level == 1 || step.flags.contains(PageTableFlags::HUGE_PAGE) {
+            return;
+        }
+        table_addr = entry.addr();
+    }
+}
+
+fn page_size_name(page_size: u64) -> &'static str {
+    match page_size {
+        0x1000 => "4K",
+        0x20_0000 => "2M",
+        0x4000_0000 => "1G",
+        _ => "?",
+    }
+}
+
+unsafe fn table_at(physical_memory_offset: VirtAddr, phys: PhysAddr) -> &'static PageTable {
+    let virt = physical_memory_offset + phys.as_u64();
+    &*virt.as_ptr::<PageTable>()
+}
+
+fn walk(
+    table: &PageTable,
+    level: u64,
+    base: u64,
+    inherited: PageTableFlags,
+    physical_memory_offset: VirtAddr,
+    out: &mut impl FnMut(MappedRange),
+) {
+    let shift = 12 + 9 * (level - 1);
+
+    for (index, entry) in table.iter().enumerate() {
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            continue;
+        }
+
+        let virt = base | ((index as u64) << shift);
+        let inherited = (inherited & flags & ALL_LEVEL_FLAGS)
+            | ((inherited | flags) & PageTableFlags::NO_EXECUTE);
+
+        // P3和P2中设置了HUGE_PAGE的项直接映射大页
+        if level == 1 || (level <= 3 && flags.contains(PageTableFlags::HUGE_PAGE)) {
+            let own_flags = flags - ALL_LEVEL_FLAGS - IGNORED_FLAGS;
+            out(MappedRange {
+                virt_start: VirtAddr::new_truncate(virt),
+                phys_start: super::huge_frame_addr(entry, 1 << shift),
+                size: 1 << shift,
+                page_size: 1 << shift,
+                flags: own_flags | inherited,
+            });
+        } else {
+            let next = unsafe { table_at(physical_memory_offset, entry.addr()) };
+            walk(next, level - 1, virt, inherited, physical_memory_offset, out);
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use core::{mem::size_of, slice};
+
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use x86_64::{
+    align_up,
+    structures::paging::{
+        FrameAllocator, FrameDeallocator, PageSize, PhysFrame, Size2MiB, Size4KiB,
+    },
+    PhysAddr, VirtAddr,
+};
+
+use crate::serial_println;
+
+const FRAME_SIZE: u64 = 4096;
+// 引用计数表中标记位于空闲链表中的帧
+const FREE: u32 = u32::MAX;
+
+// 可回收的物理帧分配器
+// 从未分配过的帧按内存映射顺序依次分配, 回收的帧组成一个链表,
+// 链表节点直接存放在空闲帧的起始位置, 通过物理内存偏移访问
+// 被多处映射共享的帧记录额外的引用计数, 引用全部释放后才回收
+// 引用计数表占用某个可用区域开头的若干帧, 不使用堆, 在持有KERNEL_MEMORY或页错误处理中也能更新
+// 该表同时记录帧是否位于空闲链表中, 用于发现重复释放
+pub struct FreeListFrameAllocator {
+    memory_map: &'static MemoryMap,
+    physical_memory_offset: VirtAddr,
+    region_index: usize,            // 当前正在分配的可用区域在内存映射中的索引
+    next: u64,                      // 该区域中下一个未分配过的帧地址
+    free_list: Option<PhysFrame>,   // 已回收帧链表的表头
+    ref_counts: &'static mut [u32], // 按帧号索引, 除首个引用外的引用数, 空闲帧为FREE
+    ref_counts_start: u64,          // 引用计数表的物理地址
+    ref_counts_size: u64,           // 引用计数表占用的字节数, 按帧对齐
+    total_frames: usize,
+    used_frames: usize,
+}
+
+// 帧分配器的统计信息
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameStats {
+    pub total_frames: usize,
+    pub used_frames: usize,
+    pub free_frames: usize,
+}
+
+impl FreeListFrameAllocator {
+    // 从传递的内存映射表中创建一个FrameAllocator
+    // 调用者需保证内存映射中标记为Usable的帧确实未被使用,
+    // 且整个物理内存已被映射到physical_memory_offset处
+    pub unsafe fn init(memory_map: &'static MemoryMap, physical_memory_offset: VirtAddr) -> Self {
+        let total_frames = memory_map
+            .iter()
+            .filter(|r| r.region_type == MemoryRegionType::Usable)
+            .map(|r| ((r.range.end_addr() - r.range.start_addr()) / FRAME_SIZE) as usize)
+            .sum();
+
+        // 引用计数表覆盖到最高的可用帧, 放在第一个足够大的可用区域开头
+        let frame_count = memory_map
+            .iter()
+            .filter(|r| r.region_type == MemoryRegionType::Usable)
+            .map(|r| r.range.end_addr() / FRAME_SIZE)
+            .max()
+            .unwrap_or(0) as usize;
+        let ref_counts_size = align_up((frame_count * size_of::<u32>()) as u64, FRAME_SIZE);
+        let ref_counts_start = memory_map
+            .iter()
+            .filter(|r| r.region_type == MemoryRegionType::Usable)
+            .find(|r| r.range.end_addr() - r.range.start_addr() >= ref_counts_size)
+            .expect("no usable region for frame reference counts")
+            .range
+            .start_addr();
+        let ref_counts_ptr: *mut u32 = (physical_memory_offset + ref_counts_start).as_mut_ptr();
+        let ref_counts = slice::from_raw_parts_mut(ref_counts_ptr, frame_count);
+        ref_counts.fill(0);
+
+        let mut allocator = FreeListFrameAllocator {
+            memory_map,
+            physical_memory_offset,
+            region_index: 0,
+            next: 0,
+            free_list: None,
+            ref_counts,
+            ref_counts_start,
+            ref_counts_size,
+            total_frames,
+            // 引用计数表占用的帧不会被回收
+            used_frames: (ref_counts_size / FRAME_SIZE) as usize,
+        };
+        allocator.seek_region(0);
+        allocator
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        FrameStats {
+            total_frames: self.total_frames,
+            used_frames: self.used_frames,
+            free_frames: self.total_frames - self.used_frames,
+        }
+    }
+
+    // 为已分配的帧增加一个引用, 用于写时复制等共享映射
+    // 之后每次deallocate_frame只减少一个引用, 不由该分配器管理的帧和空闲帧被忽略
+    pub fn share_frame(&mut self, frame: PhysFrame) {
+        if !self.manages(frame) {
+            return;
+        }
+        let extra = &mut self.ref_counts[Self::frame_index(frame)];
+        if *extra != FREE {
+            *extra += 1;
+        }
+    }
+
+    // 帧的引用计数, 空闲帧为0, 不由该分配器管理的帧总是1
+    pub fn ref_count(&self, frame: PhysFrame) -> usize {
+        match self.ref_counts.get(Self::frame_index(frame)) {
+            Some(&FREE) => 0,
+            Some(&extra) => extra as usize + 1,
+            None => 1,
+        }
+    }
+
+    fn frame_index(frame: PhysFrame) -> usize {
+        (frame.start_address().as_u64() / FRAME_SIZE) as usize
+    }
+
+    // frame是否位于可用区域中且不属于引用计数表, 只有这样的帧才由该分配器管理
+    pub fn manages(&self, frame: PhysFrame) -> bool {
+        let addr = frame.start_address().as_u64();
+        let in_table =
+            self.ref_counts_start <= addr && addr < self.ref_counts_start + self.ref_counts_size;
+        !in_table
+            && self.memory_map.iter().any(|r| {
+                r.region_type == MemoryRegionType::Usable
+                    && r.range.start_addr() <= addr
+                    && addr < r.range.end_addr()
+            })
+    }
+
+    // 将游标移动到从index开始的第一个可用区域
+    fn seek_region(&mut self, index: usize) {
+        let regions = &self.memory_map[index..];
+        match regions
+            .iter()
+            .position(|r| r.region_type == MemoryRegionType::Usable)
+        {
+            Some(offset) => {
+                self.region_index = index + offset;
+                self.next = self.memory_map[self.region_index].range.start_addr();
+                // 跳过区域开头的引用计数表
+                if self.next == self.ref_counts_start {
+                    self.next += self.ref_counts_size;
+                }
+            }
+            None => self.region_index = self.memory_map.len(),
+        }
+    }
+
+    // 从当前可用区域中取出一段按2MiB对齐的连续帧, 用于映射2MiB大页
+    // 对齐前跳过的帧放入空闲链表, 当前区域放不下时返回None, 调用者应退回到4KiB页
+    pub fn allocate_huge_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+        let region = self.memory_map.get(self.region_index)?;
+        let start = align_up(self.next, Size2MiB::SIZE);
+        let end = start.checked_add(Size2MiB::SIZE)?;
+        if end > region.range.end_addr() {
+            return None;
+        }
+
+        for addr in (self.next..start).step_by(FRAME_SIZE as usize) {
+            unsafe { self.push_free(PhysFrame::containing_address(PhysAddr::new(addr))) };
+        }
+        self.next = end;
+        self.used_frames += (Size2MiB::SIZE / FRAME_SIZE) as usize;
+        Some(PhysFrame::containing_address(PhysAddr::new(start)))
+    }
+
+    // 将帧标记为空闲并放入空闲链表
+    unsafe fn push_free(&mut self, frame: PhysFrame) {
+        self.ref_counts[Self::frame_index(frame)] = FREE;
+        self.free_node(frame).write(self.free_list);
+        self.free_list = Some(frame);
+    }
+
+    // 获取存放在空闲帧中的链表节点
+    fn free_node(&self, frame: PhysFrame) -> *mut Option<PhysFrame> {
+        let virt = self.physical_memory_offset + frame.start_address().as_u64();
+        virt.as_mut_ptr()
+    }
+
+    // 从未分配过的区域中取出一帧
+    fn allocate_fresh(&mut self) -> Option<PhysFrame> {
+        while self.region_index < self.memory_map.len() {
+            let region = &self.memory_map[self.region_index];
+            if self.next < region.range.end_addr() {
+                let frame = PhysFrame::containing_address(PhysAddr::new(self.next));
+                self.next += FRAME_SIZE;
+                return Some(frame);
+            }
+            self.seek_region(self.region_index + 1);
+        }
+
+        None
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for FreeListFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        // 优先复用已回收的帧
+        let frame = match self.free_list {
+            Some(frame) => {
+                self.free_list = unsafe { self.free_node(frame).read() };
+                self.ref_counts[Self::frame_index(frame)] = 0;
+                Some(frame)
+            }
+            None => self.allocate_fresh(),
+        };
+
+        if frame.is_some() {
+            self.used_frames += 1;
+        }
+        frame
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for FreeListFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        // 不由该分配器管理的帧和重复释放的帧都不能进入空闲链表, 否则之后会被重复分配
+        if !self.manages(frame) {
+            serial_println!("frame allocator: rejected free of unmanaged {:?}", frame);
+            return;
+        }
+        let extra = &mut self.ref_counts[Self::frame_index(frame)];
+        match *extra {
+            FREE => {
+                serial_println!("frame allocator: rejected double free of {:?}", frame);
+                return;
+            }
+            0 => {}
+            // 帧仍被其他映射共享时只减少引用计数
+            _ => {
+                *extra -= 1;
+                return;
+            }
+        }
+
+        self.push_free(frame);
+        self.used_frames -= 1;
+    }
+}
@@ -0,0 +1,137 @@
+use core::mem::{align_of, size_of};
+
+use volatile::Volatile;
+use x86_64::{
+    structures::paging::{mapper::UnmapError, Mapper, PageTableFlags, PhysFrame, Size4KiB},
+    PhysAddr, VirtAddr,
+};
+
+use super::{
+    lock_kernel_memory,
+    vma::{VirtualRegion, VmaError, KERNEL_VMM},
+};
+
+// 设备寄存器不可缓存, 也不允许执行
+pub const MMIO_FLAGS: PageTableFlags = PageTableFlags::PRESENT
+    .union(PageTableFlags::WRITABLE)
+    .union(PageTableFlags::NO_CACHE)
+    .union(PageTableFlags::WRITE_THROUGH)
+    .union(PageTableFlags::NO_EXECUTE);
+
+// 映射到内核虚拟地址空间中的一段设备内存, drop时取消映射
+// 物理帧属于设备, 不归还给帧分配器
+pub struct MmioRegion {
+    region: VirtualRegion,
+    base: VirtAddr, // phys对应的虚拟地址
+    phys: PhysAddr,
+    len: usize,
+}
+
+// 将物理地址phys开始的len字节设备内存映射到内核虚拟地址空间
+// phys不需要按页对齐
+pub fn map_mmio(phys: PhysAddr, len: usize) -> Result<MmioRegion, VmaError> {
+    let first = PhysFrame::<Size4KiB>::containing_address(phys);
+    let page_offset = phys - first.start_address();
+    let size = page_offset + len.max(1) as u64;
+    let last = PhysFrame::containing_address(first.start_address() + (size - 1));
+
+    let region = KERNEL_VMM.lock().reserve(size, 0, MMIO_FLAGS, "mmio")?;
+
+    let result = match lock_kernel_memory().as_mut() {
+        Some(memory) => region
+            .pages()
+            .zip(PhysFrame::range_inclusive(first, last))
+            .try_for_each(|(page, frame)| unsafe {
+                memory
+                    .mapper
+                    .map_to(page, frame, MMIO_FLAGS, &mut memory.frame_allocator)
+                    .map(|flush| flush.flush())
+            })
+            .map_err(VmaError::Map),
+        None => Err(VmaError::NotInitialized),
+    };
+
+    let mmio = MmioRegion {
+        region,
+        base: region.start + page_offset,
+        phys,
+        len,
+    };
+    // 映射失败时由drop撤销已映射的部分并归还地址
+    result.map(|_| mmio)
+}
+
+impl MmioRegion {
+    pub fn phys_addr(&self) -> PhysAddr {
+        self.phys
+    }
+
+    pub fn virt_addr(&self) -> VirtAddr {
+        self.base
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // 偏移offset处类型为T的寄存器
+    // offset需满足T的对齐要求, 且整个寄存器位于区域内
+    pub fn register<T: Copy>(&self, offset: usize) -> &Volatile<T> {
+        unsafe { &*self.register_ptr(offset) }
+    }
+
+    pub fn register_mut<T: Copy>(&mut self, offset: usize) -> &mut Volatile<T> {
+        unsafe { &mut *self.register_ptr(offset) }
+    }
+
+    pub fn read<T: Copy>(&self, offset: usize) -> T {
+        self.register(offset).read()
+    }
+
+    pub fn write<T: Copy>(&mut self, offset: usize, value: T) {
+        self.register_mut(offset).write(value)
+    }
+
+    // 通过共享引用读写32位寄存器, 用于中断处理等只能拿到共享引用的场合
+    pub fn read32(&self, offset: usize) -> u32 {
+        unsafe { (self.register_ptr::<u32>(offset) as *const u32).read_volatile() }
+    }
+
+    pub fn write32(&self, offset: usize, value: u32) {
+        unsafe { (self.register_ptr::<u32>(offset) as *mut u32).write_volatile(value) }
+    }
+
+    fn register_ptr<T: Copy>(&self, offset: usize) -> *mut Volatile<T> {
+        assert!(
+            offset + size_of::<T>() <= self.len,
+            "mmio access out of bounds: offset {:#x}, len {:#x}",
+            offset,
+            self.len
+        );
+        let addr = self.base + offset;
+        assert!(
+            addr.is_aligned(align_of::<T>() as u64),
+            "unaligned mmio access"
+        );
+        addr.as_mut_ptr()
+    }
+}
+
+impl Drop for MmioRegion {
+    fn drop(&mut self) {
+        if let Some(memory) = lock_kernel_memory().as_mut() {
+            for page in self.region.pages() {
+                match Mapper::<Size4KiB>::unmap(&mut memory.mapper, page) {
+                    Ok((_, flush)) => flush.flush(),
+                    Err(UnmapError::PageNotMapped) => {}
+                    Err(err) => panic!("failed to unmap mmio page {:?}: {:?}", page, err),
+                }
+            }
+        }
+        let _ = KERNEL_VMM.lock().release(self.region.start);
+    }
+}
@@ -0,0 +1,137 @@
+use core::{ptr, slice};
+
+use x86_64::{
+    registers::{
+        control::{Cr0, Cr0Flags},
+        model_specific::{Efer, EferFlags},
+    },
+    structures::paging::{mapper::FlagUpdateError, Mapper, Page, PageTableFlags, Size4KiB},
+    VirtAddr,
+};
+
+// 由链接器定义, 位于内核ELF文件头的起始处
+extern "C" {
+    static __ehdr_start: u8;
+}
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+
+#[repr(C)]
+struct ElfHeader {
+    ident: [u8; 16],
+    kind: u16,
+    machine: u16,
+    version: u32,
+    entry: u64,
+    phoff: u64,
+    shoff: u64,
+    flags: u32,
+    ehsize: u16,
+    phentsize: u16,
+    phnum: u16,
+    shentsize: u16,
+    shnum: u16,
+    shstrndx: u16,
+}
+
+#[repr(C)]
+struct ProgramHeader {
+    kind: u32,
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    paddr: u64,
+    filesz: u64,
+    memsz: u64,
+    align: u64,
+}
+
+// 内核镜像中一个可加载的段, 对应.text, .rodata或.data/.bss
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelSegment {
+    pub start: VirtAddr,
+    pub size: u64,
+    pub flags: PageTableFlags, // 该段应有的页表权限
+}
+
+impl KernelSegment {
+    pub fn end(&self) -> VirtAddr {
+        self.start + self.size
+    }
+
+    pub fn overlaps(&self, page: Page) -> bool {
+        self.start < page.start_address() + page.size() && page.start_address() < self.end()
+    }
+
+    pub fn pages(&self) -> impl Iterator<Item = Page> {
+        let start = Page::containing_address(self.start);
+        let end = Page::containing_address(self.end() - 1u64);
+        Page::range_inclusive(start, end)
+    }
+}
+
+// 启用EFER.NXE使页表中的NO_EXECUTE生效, 启用CR0.WP使只读页在内核态下也不可写
+pub fn enable_nx_and_write_protect() {
+    unsafe {
+        Efer::update(|flags| flags.insert(EferFlags::NO_EXECUTE_ENABLE));
+        Cr0::update(|flags| flags.insert(Cr0Flags::WRITE_PROTECT));
+    }
+}
+
+// 从内存中的内核ELF程序头读取所有可加载的段
+pub fn kernel_segments() -> impl Iterator<Item = KernelSegment> {
+    let base = unsafe { ptr::addr_of!(__ehdr_start) };
+    let header = unsafe { &*(base as *const ElfHeader) };
+    assert_eq!(header.ident[..4], ELF_MAGIC, "kernel ELF header not mapped");
+
+    let program_headers = unsafe {
+        slice::from_raw_parts(
+            base.add(header.phoff as usize) as *const ProgramHeader,
+            header.phnum as usize,
+        )
+    };
+    program_headers
+        .iter()
+        .filter(|header| header.kind == PT_LOAD && header.memsz > 0)
+        .map(|header| KernelSegment {
+            start: VirtAddr::new(header.vaddr),
+            size: header.memsz,
+            flags: segment_flags(header.flags),
+        })
+}
+
+fn segment_flags(elf_flags: u32) -> PageTableFlags {
+    let mut flags = PageTableFlags::PRESENT;
+    if elf_flags & PF_W != 0 {
+        flags |= PageTableFlags::WRITABLE;
+    }
+    if elf_flags & PF_X == 0 {
+        flags |= PageTableFlags::NO_EXECUTE;
+    }
+    flags
+}
+
+// 按段重新设置内核镜像的页表权限: .text只读可执行, .rodata只读不可执行, .data/.bss可写不可执行
+// 两个段共用的页取两者权限的并集
+pub fn protect_kernel_sections(mapper: &mut impl Mapper<Size4KiB>) -> Result<(), FlagUpdateError> {
+    enable_nx_and_write_protect();
+
+    for segment in kernel_segments() {
+        for page in segment.pages() {
+            let flags = kernel_segments().filter(|other| other.overlaps(page)).fold(
+                PageTableFlags::PRESENT | PageTableFlags::NO_EXECUTE,
+                |flags, other| {
+                    let writable = (flags | other.flags) & PageTableFlags::WRITABLE;
+                    let no_execute = flags & other.flags & PageTableFlags::NO_EXECUTE;
+                    PageTableFlags::PRESENT | writable | no_execute
+                },
+            );
+            unsafe { mapper.update_flags(page, flags)?.flush() };
+        }
+    }
+
+    Ok(())
+}
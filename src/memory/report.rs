@@ -0,0 +1,100 @@
+use core::fmt;
+
+use bootloader::bootinfo::{MemoryMap, MemoryRegion, MemoryRegionType};
+
+use crate::{allocator, println, serial_println};
+
+const FRAME_SIZE: u64 = 4096;
+
+// 物理内存使用情况的汇总
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemorySummary {
+    pub total_bytes: u64,      // 内存映射中所有区域的大小之和
+    pub usable_bytes: u64,     // 启动时可供帧分配器使用的内存
+    pub kernel_bytes: u64,     // 内核镜像, 内核栈以及启动时建立的页表
+    pub bootloader_bytes: u64, // 引导程序及其传递的启动信息
+    pub heap_frames: usize,    // 当前堆映射的物理帧数
+}
+
+pub fn region_size(region: &MemoryRegion) -> u64 {
+    region.range.end_addr() - region.range.start_addr()
+}
+
+// 内存映射中某一类型区域的总大小
+pub fn bytes_of(memory_map: &MemoryMap, region_type: MemoryRegionType) -> u64 {
+    memory_map
+        .iter()
+        .filter(|region| region.region_type == region_type)
+        .map(region_size)
+        .sum()
+}
+
+pub fn summary(memory_map: &MemoryMap) -> MemorySummary {
+    let bytes_of_all = |types: &[MemoryRegionType]| -> u64 {
+        types
+            .iter()
+            .map(|&region_type| bytes_of(memory_map, region_type))
+            .sum()
+    };
+
+    MemorySummary {
+        total_bytes: memory_map.iter().map(region_size).sum(),
+        usable_bytes: bytes_of(memory_map, MemoryRegionType::Usable),
+        kernel_bytes: bytes_of_all(&[
+            MemoryRegionType::Kernel,
+            MemoryRegionType::KernelStack,
+            MemoryRegionType::PageTable,
+        ]),
+        bootloader_bytes: bytes_of_all(&[
+            MemoryRegionType::Bootloader,
+            MemoryRegionType::BootInfo,
+            MemoryRegionType::Package,
+        ]),
+        heap_frames: (allocator::heap_stats().heap_size as u64 / FRAME_SIZE) as usize,
+    }
+}
+
+// 以KiB/MiB为单位显示字节数
+struct Size(u64);
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 >= 1024 * 1024 {
+            write!(f, "{}MiB", self.0 / (1024 * 1024))
+        } else {
+            write!(f, "{}KiB", self.0 / 1024)
+        }
+    }
+}
+
+// 以普通颜色同时输出到屏幕和串口
+macro_rules! print_line {
+    ($($arg:tt)*) => {{
+        println!($($arg)*);
+        serial_println!($($arg)*);
+    }};
+}
+
+// 输出内存映射中的每个区域以及使用情况汇总
+pub fn print_memory_map(memory_map: &MemoryMap) {
+    print_line!("physical memory map:");
+    for region in memory_map.iter() {
+        print_line!(
+            "  {:#012x}-{:#012x} {:>8} {:?}",
+            region.range.start_addr(),
+            region.range.end_addr(),
+            Size(region_size(region)),
+            region.region_type,
+        );
+    }
+
+    let summary = summary(memory_map);
+    print_line!(
+        "usable {}, kernel {}, bootloader {}, heap {} frames ({})",
+        Size(summary.usable_bytes),
+        Size(summary.kernel_bytes),
+        Size(summary.bootloader_bytes),
+        summary.heap_frames,
+        Size(summary.heap_frames as u64 * FRAME_SIZE),
+    );
+}
@@ -0,0 +1,388 @@
+use alloc::collections::BTreeMap;
+
+use spin::Mutex;
+use x86_64::{
+    structures::idt::PageFaultErrorCode,
+    structures::paging::{
+        mapper::{MapToError, UnmapError},
+        FrameAllocator, FrameDeallocator, Mapper, Page, PageSize, PageTableFlags, Size4KiB,
+    },
+    align_up, VirtAddr,
+};
+
+use super::{
+    cow::{self, CowError},
+    lock_kernel_memory, KERNEL_MEMORY,
+};
+
+// 由虚拟地址管理器分配的内核虚拟地址范围, 位于高半部分的起始处
+pub const KERNEL_VMA_START: u64 = 0xffff_8000_0000_0000;
+pub const KERNEL_VMA_END: u64 = 0xffff_9000_0000_0000;
+
+const PAGE_SIZE: u64 = Size4KiB::SIZE;
+
+// 一段已分配的虚拟地址区域
+// 区域底部guard_size字节为不映射的保护页, 用于捕获栈溢出
+// lazy区域在分配时不映射, 首次访问某页时才由页错误处理映射一个清零的物理帧
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VirtualRegion {
+    pub name: &'static str,
+    pub start: VirtAddr,
+    pub size: u64,
+    pub guard_size: u64,
+    pub flags: PageTableFlags,
+    pub lazy: bool,
+}
+
+impl VirtualRegion {
+    pub fn end(&self) -> VirtAddr {
+        self.start + self.size
+    }
+
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        self.start <= addr && addr < self.end()
+    }
+
+    pub fn in_guard(&self, addr: VirtAddr) -> bool {
+        self.start <= addr && addr < self.start + self.guard_size
+    }
+
+    // 区域中需要映射的页, 不包括保护页
+    pub fn pages(&self) -> impl Iterator<Item = Page> {
+        let start = Page::containing_address(self.start + self.guard_size);
+        let end = Page::containing_address(self.end() - 1u64);
+        Page::range_inclusive(start, end)
+    }
+}
+
+#[derive(Debug)]
+pub enum VmaError {
+    OutOfVirtualMemory,
+    NotFound,
+    Overlap,
+    NotInitialized, // memory::KERNEL_MEMORY尚未初始化
+    Map(MapToError<Size4KiB>),
+    Unmap(UnmapError),
+    Cow(CowError),
+}
+
+// 管理一段虚拟地址空间, 保证分配出的区域互不重叠
+pub struct VirtualMemoryManager {
+    start: u64,
+    end: u64,
+    regions: BTreeMap<u64, VirtualRegion>, // 以起始地址为键
+}
+
+impl VirtualMemoryManager {
+    pub const fn new(start: u64, end: u64) -> Self {
+        VirtualMemoryManager {
+            start,
+            end,
+            regions: BTreeMap::new(),
+        }
+    }
+
+    // 按首次适配找到一段足够大的空闲虚拟地址, 只记录而不映射
+    // 返回的区域大小包括底部guard_size字节的保护页
+    pub fn reserve(
+        &mut self,
+        size: u64,
+        guard_size: u64,
+        flags: PageTableFlags,
+        name: &'static str,
+    ) -> Result<VirtualRegion, VmaError> {
+        let guard_size = align_up(guard_size, PAGE_SIZE);
+        let size = align_up(size.max(1), PAGE_SIZE) + guard_size;
+
+        let mut candidate = self.start;
+        for region in self.regions.range(self.start..self.end).map(|(_, r)| r) {
+            if candidate + size <= region.start.as_u64() {
+                break;
+            }
+            candidate = region.end().as_u64();
+        }
+        if candidate + size > self.end {
+            return Err(VmaError::OutOfVirtualMemory);
+        }
+
+        let region = VirtualRegion {
+            name,
+            start: VirtAddr::new(candidate),
+            size,
+            guard_size,
+            flags,
+            lazy: false,
+        };
+        self.regions.insert(candidate, region);
+        Ok(region)
+    }
+
+    // 登记一段已由其他方式映射的区域, 例如静态分配的中断栈
+    pub fn insert(&mut self, region: VirtualRegion) -> Result<(), VmaError> {
+        let overlaps = self
+            .regions
+            .values()
+            .any(|r| r.start < region.end() && region.start < r.end());
+        if overlaps {
+            return Err(VmaError::Overlap);
+        }
+
+        self.regions.insert(region.start.as_u64(), region);
+        Ok(())
+    }
+
+    // 移除起始地址为start的区域记录
+    pub fn release(&mut self, start: VirtAddr) -> Result<VirtualRegion, VmaError> {
+        self.regions
+            .remove(&start.as_u64())
+            .ok_or(VmaError::NotFound)
+    }
+
+    // 查找包含addr的区域
+    pub fn find(&self, addr: VirtAddr) -> Option<&VirtualRegion> {
+        self.regions
+            .range(..=addr.as_u64())
+            .next_back()
+            .map(|(_, region)| region)
+            .filter(|region| region.contains(addr))
+    }
+
+    pub fn regions(&self) -> impl Iterator<Item = &VirtualRegion> {
+        self.regions.values()
+    }
+}
+
+pub static KERNEL_VMM: Mutex<VirtualMemoryManager> =
+    Mutex::new(VirtualMemoryManager::new(KERNEL_VMA_START, KERNEL_VMA_END));
+
+// 为区域中的每一页分配物理帧并以区域的标志映射
+pub fn map_region(
+    region: &VirtualRegion,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
+    for page in region.pages() {
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or(MapToError::FrameAllocationFailed)?;
+        unsafe {
+            mapper
+                .map_to(page, frame, region.flags, frame_allocator)?
+                .flush()
+        };
+    }
+
+    Ok(())
+}
+
+// 取消区域中所有页的映射并释放对应的物理帧, 跳过未映射的页
+pub fn unmap_region(
+    region: &VirtualRegion,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameDeallocator<Size4KiB>,
+) -> Result<(), UnmapError> {
+    for page in region.pages() {
+        match mapper.unmap(page) {
+            Ok((frame, flush)) => {
+                flush.flush();
+                unsafe { frame_allocator.deallocate_frame(frame) };
+            }
+            Err(UnmapError::PageNotMapped) => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(())
+}
+
+// 在内核虚拟地址空间中分配并映射一段区域
+pub fn allocate_region(
+    size: u64,
+    flags: PageTableFlags,
+    name: &'static str,
+) -> Result<VirtualRegion, VmaError> {
+    allocate_guarded_region(size, 0, flags, name)
+}
+
+// 分配一个内核栈, 栈底之下有一个不映射的保护页
+// 栈向下增长, 栈顶为返回区域的end()
+pub fn allocate_stack(size: u64, name: &'static str) -> Result<VirtualRegion, VmaError> {
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    allocate_guarded_region(size, PAGE_SIZE, flags, name)
+}
+
+fn allocate_guarded_region(
+    size: u64,
+    guard_size: u64,
+    flags: PageTableFlags,
+    name: &'static str,
+) -> Result<VirtualRegion, VmaError> {
+    let region = KERNEL_VMM.lock().reserve(size, guard_size, flags, name)?;
+
+    let result = match lock_kernel_memory().as_mut() {
+        Some(memory) => map_region(&region, &mut memory.mapper, &mut memory.frame_allocator)
+            .map_err(VmaError::Map),
+        None => Err(VmaError::NotInitialized),
+    };
+
+    // 映射失败时撤销已映射的部分并归还地址
+    if let Err(err) = result {
+        let _ = free_region(region.start);
+        return Err(err);
+    }
+
+    Ok(region)
+}
+
+// 预留一段按需映射的区域, 只有被访问的页才会占用物理帧
+// 可以用free_region释放
+pub fn reserve_lazy_region(
+    size: u64,
+    flags: PageTableFlags,
+    name: &'static str,
+) -> Result<VirtualRegion, VmaError> {
+    let mut vmm = KERNEL_VMM.lock();
+    let mut region = vmm.reserve(size, 0, flags, name)?;
+    region.lazy = true;
+    vmm.regions.insert(region.start.as_u64(), region);
+    Ok(region)
+}
+
+// 处理lazy区域中未映射页的访问, 映射一个清零的物理帧
+// 返回true表示错误已解决, 可以重新执行触发错误的指令
+// 在页错误处理中调用, 锁被占用时放弃处理
+pub fn handle_lazy_fault(addr: VirtAddr, error_code: PageFaultErrorCode) -> bool {
+    // 页已存在时的错误是权限错误, 不能通过映射解决
+    if error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+        return false;
+    }
+
+    let region = match KERNEL_VMM.try_lock() {
+        Some(vmm) => match vmm.find(addr) {
+            Some(region) if region.lazy && !region.in_guard(addr) => *region,
+            _ => return false,
+        },
+        None => return false,
+    };
+
+    let mut kernel_memory = match KERNEL_MEMORY.try_lock() {
+        Some(kernel_memory) => kernel_memory,
+        None => return false,
+    };
+    let memory = match kernel_memory.as_mut() {
+        Some(memory) => memory,
+        None => return false,
+    };
+
+    let frame = match memory.frame_allocator.allocate_frame() {
+        Some(frame) => frame,
+        None => return false,
+    };
+    let frame_ptr: *mut u8 = (memory.mapper.phys_offset() + frame.start_address().as_u64())
+        .as_mut_ptr();
+    unsafe { frame_ptr.write_bytes(0, PAGE_SIZE as usize) };
+
+    let page = Page::containing_address(addr);
+    let result = unsafe {
+        memory
+            .mapper
+            .map_to(page, frame, region.flags, &mut memory.frame_allocator)
+    };
+    match result {
+        Ok(flush) => {
+            flush.flush();
+            true
+        }
+        Err(_) => {
+            unsafe { memory.frame_allocator.deallocate_frame(frame) };
+            false
+        }
+    }
+}
+
+// 以写时复制的方式复制start处的区域, 返回的新区域与原区域共享物理帧
+// 原区域中未映射的页(例如lazy区域中未访问过的页)在新区域中同样不映射
+pub fn share_region(start: VirtAddr, name: &'static str) -> Result<VirtualRegion, VmaError> {
+    let (source, copy) = {
+        let mut vmm = KERNEL_VMM.lock();
+        let source = *vmm.find(start).ok_or(VmaError::NotFound)?;
+        let mut copy = vmm.reserve(
+            source.size - source.guard_size,
+            source.guard_size,
+            source.flags,
+            name,
+        )?;
+        copy.lazy = source.lazy;
+        vmm.regions.insert(copy.start.as_u64(), copy);
+        (source, copy)
+    };
+
+    let result = match lock_kernel_memory().as_mut() {
+        Some(memory) => source
+            .pages()
+            .zip(copy.pages())
+            .try_for_each(|(src, dst)| {
+                match cow::share_page(src, dst, &mut memory.mapper, &mut memory.frame_allocator) {
+                    Err(CowError::NotMapped) => Ok(()),
+                    result => result,
+                }
+            })
+            .map_err(VmaError::Cow),
+        None => Err(VmaError::NotInitialized),
+    };
+
+    if let Err(err) = result {
+        let _ = free_region(copy.start);
+        return Err(err);
+    }
+
+    Ok(copy)
+}
+
+// 取消映射并释放由allocate_region分配的区域
+pub fn free_region(start: VirtAddr) -> Result<(), VmaError> {
+    let region = KERNEL_VMM.lock().release(start)?;
+
+    match lock_kernel_memory().as_mut() {
+        Some(memory) => unmap_region(&region, &mut memory.mapper, &mut memory.frame_allocator)
+            .map_err(VmaError::Unmap),
+        None => Err(VmaError::NotInitialized),
+    }
+}
+
+// 将一段已映射的栈的最低一页取消映射作为保护页, 并登记到KERNEL_VMM
+// 保护页的物理帧不归还给帧分配器, 调用者需保证该页不再被使用
+pub unsafe fn guard_existing_stack(
+    name: &'static str,
+    bottom: VirtAddr,
+    size: u64,
+) -> Result<(), VmaError> {
+    let guard: Page = Page::from_start_address(bottom).map_err(|_| VmaError::NotFound)?;
+
+    match lock_kernel_memory().as_mut() {
+        Some(memory) => match memory.mapper.unmap(guard) {
+            Ok((_, flush)) => flush.flush(),
+            Err(UnmapError::PageNotMapped) => {}
+            Err(err) => return Err(VmaError::Unmap(err)),
+        },
+        None => return Err(VmaError::NotInitialized),
+    }
+
+    KERNEL_VMM.lock().insert(VirtualRegion {
+        name,
+        start: bottom,
+        size,
+        guard_size: PAGE_SIZE,
+        flags: PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
+        lazy: false,
+    })
+}
+
+// 若addr位于某个区域的保护页中, 返回该区域的名字
+// 在异常处理中调用, 因此不等待锁
+pub fn guard_page_owner(addr: VirtAddr) -> Option<&'static str> {
+    let vmm = KERNEL_VMM.try_lock()?;
+    vmm.find(addr)
+        .filter(|region| region.in_guard(addr))
+        .map(|region| region.name)
+}
@@ -34,3 +34,12 @@ macro_rules! serial_println {
     ($fmt:expr, $($arg:tt)*) => ($crate::serial_print!(
         concat!($fmt, "\n"), $($arg)*));
 }
+
+// 同时输出到屏幕和串口, 用于异常, 分配失败等需要在两处都能看到的报告
+#[macro_export]
+macro_rules! report {
+    ($($arg:tt)*) => {{
+        $crate::warn!($($arg)*);
+        $crate::serial_println!($($arg)*);
+    }};
+}
@@ -0,0 +1,214 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rust_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use x86_64::{
+    structures::paging::{
+        FrameAllocator, FrameDeallocator, Mapper, Page, PageTableFlags, PhysFrame, Translate,
+    },
+    PhysAddr, VirtAddr,
+};
+
+use rust_os::{
+    allocator,
+    memory::{
+        self,
+        address_space::{self, AddressSpace},
+        frame_allocator::FreeListFrameAllocator,
+        vma,
+    },
+};
+
+entry_point!(main);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    rust_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator =
+        unsafe { FreeListFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+// 位于低半部分, 不与内核的任何映射重叠
+const USER_ADDR: u64 = 0x0000_6000_0000_0000;
+
+fn is_mapped_in_kernel(addr: VirtAddr) -> bool {
+    let kernel_memory = memory::KERNEL_MEMORY.lock();
+    kernel_memory
+        .as_ref()
+        .unwrap()
+        .mapper
+        .translate_addr(addr)
+        .is_some()
+}
+
+fn used_frames() -> usize {
+    let kernel_memory = memory::KERNEL_MEMORY.lock();
+    kernel_memory
+        .as_ref()
+        .unwrap()
+        .frame_allocator
+        .stats()
+        .used_frames
+}
+
+fn map_user_pages(space: &mut AddressSpace, count: u64) {
+    let flags =
+        PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+    space
+        .with_mapper(VirtAddr::new(USER_ADDR), count * 4096, |mapper, frame_allocator| {
+            for index in 0..count {
+                let page = Page::containing_address(VirtAddr::new(USER_ADDR + index * 4096));
+                let frame = frame_allocator.allocate_frame().unwrap();
+                unsafe {
+                    mapper
+                        .map_to(page, frame, flags, frame_allocator)
+                        .unwrap()
+                        .flush()
+                };
+            }
+        })
+        .unwrap();
+}
+
+#[test_case]
+fn kernel_mappings_are_shared() {
+    let space = AddressSpace::new().unwrap();
+    let boxed = Box::new(41);
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    let region = vma::allocate_region(4096, flags, "shared").unwrap();
+    let ptr: *mut u64 = region.start.as_mut_ptr();
+
+    unsafe {
+        space.switch_to().unwrap();
+        assert!(space.is_active());
+        assert_eq!(*boxed + 1, 42);
+        ptr.write_volatile(7);
+        address_space::switch_to_kernel().unwrap();
+        assert_eq!(ptr.read_volatile(), 7);
+    }
+    assert!(!space.is_active());
+
+    vma::free_region(region.start).unwrap();
+}
+
+#[test_case]
+fn user_mappings_are_private() {
+    let addr = VirtAddr::new(USER_ADDR);
+    assert!(addr.as_u64() < address_space::USER_SPACE_END);
+    assert!(!is_mapped_in_kernel(addr));
+
+    let mut space = AddressSpace::new().unwrap();
+    map_user_pages(&mut space, 1);
+    assert!(!is_mapped_in_kernel(addr));
+
+    let ptr: *mut u64 = addr.as_mut_ptr();
+    unsafe {
+        space.switch_to().unwrap();
+        ptr.write_volatile(0x1234);
+        assert_eq!(ptr.read_volatile(), 0x1234);
+        address_space::switch_to_kernel().unwrap();
+    }
+    assert!(!is_mapped_in_kernel(addr));
+}
+
+#[test_case]
+fn drop_frees_user_frames() {
+    // 第一次创建时会为内核高半部分分配页表, 不计入
+    drop(AddressSpace::new().unwrap());
+
+    let before = used_frames();
+    let mut space = AddressSpace::new().unwrap();
+    map_user_pages(&mut space, 3);
+    // P4, P3, P2, P1各一帧, 以及映射的3帧
+    assert_eq!(used_frames(), before + 4 + 3);
+
+    unsafe { space.switch_to().unwrap() };
+    drop(space);
+    assert_eq!(used_frames(), before);
+}
+
+#[test_case]
+fn drop_keeps_borrowed_frames() {
+    drop(AddressSpace::new().unwrap());
+
+    let before = used_frames();
+    let mut space = AddressSpace::new().unwrap();
+    let owned = space
+        .with_mapper(VirtAddr::new(USER_ADDR), 2 * 4096, |mapper, frame_allocator| {
+            let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+            let vga: PhysFrame = PhysFrame::containing_address(PhysAddr::new(0xb8000));
+            let page = Page::containing_address(VirtAddr::new(USER_ADDR));
+            unsafe {
+                mapper
+                    .map_to(page, vga, memory::mmio::MMIO_FLAGS, frame_allocator)
+                    .unwrap()
+                    .flush();
+            }
+
+            // 其他地方拥有的帧通过map_physical_range映射
+            let owned: PhysFrame = frame_allocator.allocate_frame().unwrap();
+            unsafe {
+                memory::map_physical_range(
+                    VirtAddr::new(USER_ADDR + 4096),
+                    owned.start_address(),
+                    4096,
+                    flags,
+                    mapper,
+                    frame_allocator,
+                )
+                .unwrap();
+            }
+            owned
+        })
+        .unwrap();
+    // P4, P3, P2, P1各一帧, 以及映射的帧
+    assert_eq!(used_frames(), before + 4 + 1);
+
+    drop(space);
+    // 只释放页表, 设备内存和映射的帧都不回收
+    assert_eq!(used_frames(), before + 1);
+
+    let mut kernel_memory = memory::KERNEL_MEMORY.lock();
+    unsafe {
+        kernel_memory
+            .as_mut()
+            .unwrap()
+            .frame_allocator
+            .deallocate_frame(owned)
+    };
+}
+
+#[test_case]
+fn with_mapper_rejects_shared_entries() {
+    use address_space::AddressSpaceError;
+
+    let mut space = AddressSpace::new().unwrap();
+    // 堆位于低半部分, 所在的P4项与内核共享
+    let heap = VirtAddr::new(allocator::HEAP_START as u64);
+    // 超出低半部分的范围
+    let last_page = VirtAddr::new(address_space::USER_SPACE_END - 4096);
+    for (start, size) in [(heap, 4096), (last_page, 2 * 4096)] {
+        let result = space.with_mapper(start, size, |_, _| ());
+        assert!(matches!(result, Err(AddressSpaceError::SharedRange)));
+    }
+    assert!(space.with_mapper(VirtAddr::new(USER_ADDR), 4096, |_, _| ()).is_ok());
+}
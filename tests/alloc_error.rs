@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::{
+    fmt::{self, Write},
+    panic::PanicInfo,
+};
+
+use bootloader::{entry_point, BootInfo};
+use x86_64::VirtAddr;
+
+use rust_os::{
+    allocator::{self, HEAP_MAX_SIZE},
+    exit_qemu,
+    memory::{self, frame_allocator::FreeListFrameAllocator},
+    serial_print, serial_println, QemuExitCode,
+};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("alloc_error::alloc_error... ");
+
+    rust_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator =
+        unsafe { FreeListFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    // 超出堆的最大大小, 应进入alloc_error_handler并panic
+    let vec: Vec<u8> = Vec::with_capacity(2 * HEAP_MAX_SIZE);
+    serial_println!("[allocation did not fail: {:p}]", vec.as_ptr());
+    exit_qemu(QemuExitCode::Failed);
+
+    loop {}
+}
+
+// 检查panic信息是否以指定前缀开头, 无需在堆上格式化
+struct PrefixMatcher {
+    prefix: &'static str,
+    matched: usize,
+    mismatch: bool,
+}
+
+impl Write for PrefixMatcher {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let rest = &self.prefix[self.matched..];
+        let len = rest.len().min(s.len());
+        if rest.as_bytes()[..len] != s.as_bytes()[..len] {
+            self.mismatch = true;
+            return Err(fmt::Error);
+        }
+        self.matched += len;
+        Ok(())
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let mut matcher = PrefixMatcher {
+        prefix: "allocation error",
+        matched: 0,
+        mismatch: false,
+    };
+    let _ = write!(matcher, "{}", info.message());
+
+    // 其他原因的panic不能算作通过
+    if matcher.mismatch || matcher.matched < matcher.prefix.len() {
+        serial_println!("[failed]");
+        serial_println!("{}", info);
+        exit_qemu(QemuExitCode::Failed);
+    } else {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    }
+    loop {}
+}
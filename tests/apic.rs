@@ -0,0 +1,97 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rust_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::{arch::x86_64::_rdtsc, panic::PanicInfo};
+
+use bootloader::{entry_point, BootInfo};
+use x86_64::{PhysAddr, VirtAddr};
+
+use rust_os::{
+    acpi, allocator,
+    interrupts::{apic, irq, InterruptIndex, PICS},
+    memory::{self, frame_allocator::FreeListFrameAllocator},
+};
+
+entry_point!(main);
+
+// 等待一次时钟中断的TSC周期数上限, 远大于PIT的默认周期(约55ms)
+const TIMER_TIMEOUT_CYCLES: u64 = 10_000_000_000;
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    rust_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator =
+        unsafe { FreeListFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+// 等待时钟中断线的计数增加, 超过TSC期限仍未等到时返回false
+fn wait_for_timer() -> bool {
+    let timer = InterruptIndex::Timer.irq();
+    let start = irq::irq_count(timer);
+    let deadline = unsafe { _rdtsc() } + TIMER_TIMEOUT_CYCLES;
+    while irq::irq_count(timer) == start {
+        if unsafe { _rdtsc() } > deadline {
+            return false;
+        }
+        core::hint::spin_loop();
+    }
+    true
+}
+
+#[test_case]
+fn madt_describes_interrupt_controllers() {
+    let madt = acpi::madt().expect("MADT not found");
+    assert!(PhysAddr::new(madt.local_apic_address).is_aligned(4096u64));
+    assert!(!madt.io_apics.is_empty());
+    assert!(madt.local_apics.iter().any(|local_apic| local_apic.enabled));
+}
+
+#[test_case]
+fn init_replaces_8259() {
+    apic::init().expect("APIC initialization failed");
+    assert!(apic::is_enabled());
+    assert_eq!(unsafe { PICS.lock().read_masks() }, [0xff, 0xff]);
+
+    let madt = acpi::madt().unwrap();
+    let local_apic = apic::local_apic().unwrap();
+    assert!(madt
+        .local_apics
+        .iter()
+        .any(|entry| entry.apic_id == local_apic.id()));
+
+    // 重复调用不会重新初始化
+    apic::init().unwrap();
+    assert_eq!(apic::local_apic().unwrap().id(), local_apic.id());
+}
+
+// 8259已被全部屏蔽, 时钟中断线的计数仍然增加说明中断经由I/O APIC送达且EOI正确
+#[test_case]
+fn timer_interrupts_arrive_through_io_apic() {
+    apic::init().expect("APIC initialization failed");
+    let timer = InterruptIndex::Timer.irq();
+    assert!(irq::handler_count(timer) > 0);
+
+    for _ in 0..3 {
+        assert!(wait_for_timer(), "timer interrupt not delivered");
+    }
+}
+
+#[test_case]
+fn irq_lines_keep_their_vectors() {
+    assert_eq!(InterruptIndex::Timer.irq(), 0);
+    assert_eq!(InterruptIndex::Keyboard.irq(), 1);
+}
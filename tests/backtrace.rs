@@ -0,0 +1,76 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rust_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::{hint::black_box, panic::PanicInfo};
+
+use bootloader::{entry_point, BootInfo};
+use x86_64::VirtAddr;
+
+use rust_os::{
+    backtrace::{symbols, Backtrace},
+    memory,
+};
+
+entry_point!(main);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    rust_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    unsafe { memory::init(phys_mem_offset) };
+
+    test_main();
+    loop {}
+}
+
+#[inline(never)]
+fn outer_frame() -> Backtrace {
+    black_box(inner_frame())
+}
+
+#[inline(never)]
+fn inner_frame() -> Backtrace {
+    black_box(Backtrace::capture())
+}
+
+#[test_case]
+fn capture_walks_nested_frames() {
+    let backtrace = outer_frame();
+    assert!(backtrace.addresses().len() >= 3);
+    assert!(backtrace.addresses().iter().all(|&addr| addr != 0));
+}
+
+#[test_case]
+fn frames_resolve_to_function_names() {
+    assert!(symbols::symbol_count() > 0, "kernel symbol table is empty");
+
+    let backtrace = outer_frame();
+    let inner = backtrace.symbol(0).expect("caller not resolved");
+    let outer = backtrace.symbol(1).expect("caller's caller not resolved");
+    assert!(inner.name.ends_with("inner_frame"), "{}", inner.name);
+    assert!(outer.name.ends_with("outer_frame"), "{}", outer.name);
+}
+
+#[test_case]
+fn context_backtrace_starts_at_instruction_pointer() {
+    let rip = inner_frame as fn() -> Backtrace as usize as u64;
+    let backtrace = Backtrace::from_context(rip, 0);
+    assert_eq!(backtrace.addresses(), &[rip]);
+    let symbol = backtrace
+        .symbol(0)
+        .expect("instruction pointer not resolved");
+    assert_eq!(symbol.addr, rip);
+}
+
+#[test_case]
+fn unknown_addresses_do_not_resolve() {
+    assert_eq!(symbols::resolve(0), None);
+    assert_eq!(symbols::resolve(0xffff_ffff_ffff_f000), None);
+}
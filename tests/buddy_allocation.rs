@@ -0,0 +1,85 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rust_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::{
+    structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size2MiB},
+    VirtAddr,
+};
+
+use rust_os::memory::buddy::{block_size, BuddyFrameAllocator, HUGE_FRAME_ORDER, MAX_ORDER};
+
+entry_point!(main);
+
+lazy_static! {
+    static ref BUDDY_ALLOCATOR: Mutex<Option<BuddyFrameAllocator>> = Mutex::new(None);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    rust_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let buddy_allocator =
+        unsafe { BuddyFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    *BUDDY_ALLOCATOR.lock() = Some(buddy_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[test_case]
+fn blocks_are_aligned() {
+    let mut guard = BUDDY_ALLOCATOR.lock();
+    let allocator = guard.as_mut().unwrap();
+
+    for order in 0..4 {
+        let addr = allocator.allocate(order).unwrap();
+        assert!(addr.is_aligned(block_size(order)));
+        unsafe { allocator.deallocate(addr, order) };
+    }
+}
+
+#[test_case]
+fn huge_frame() {
+    let mut guard = BUDDY_ALLOCATOR.lock();
+    let allocator = guard.as_mut().unwrap();
+
+    let frame: PhysFrame<Size2MiB> = allocator.allocate_frame().unwrap();
+    assert!(frame.start_address().is_aligned(block_size(HUGE_FRAME_ORDER)));
+    unsafe { allocator.deallocate_frame(frame) };
+}
+
+#[test_case]
+fn coalesce_on_free() {
+    let mut guard = BUDDY_ALLOCATOR.lock();
+    let allocator = guard.as_mut().unwrap();
+
+    let mut before = [0; MAX_ORDER + 1];
+    for (order, count) in before.iter_mut().enumerate() {
+        *count = allocator.free_blocks(order);
+    }
+    let stats = allocator.stats();
+
+    // 以一个1阶块分配, 以两个0阶块释放, 两者应重新合并
+    let addr = allocator.allocate(1).unwrap();
+    unsafe {
+        allocator.deallocate(addr, 0);
+        allocator.deallocate(addr + block_size(0), 0);
+    }
+
+    for (order, &count) in before.iter().enumerate() {
+        assert_eq!(allocator.free_blocks(order), count);
+    }
+    assert_eq!(allocator.stats(), stats);
+}
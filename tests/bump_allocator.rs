@@ -0,0 +1,103 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rust_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    panic::PanicInfo,
+    ptr,
+};
+
+use rust_os::allocator::{
+    bump::{Arena, BumpAllocator},
+    Locked,
+};
+
+const REGION_SIZE: usize = 1024;
+
+#[repr(align(16))]
+struct Region([u8; REGION_SIZE]);
+
+static mut REGION: Region = Region([0; REGION_SIZE]);
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    test_main();
+
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::test_panic_handler(info)
+}
+
+fn region() -> &'static mut [u8] {
+    unsafe { &mut (*ptr::addr_of_mut!(REGION)).0 }
+}
+
+#[test_case]
+fn arena_alignment() {
+    let mut arena = Arena::new(region());
+
+    let byte = arena.alloc(Layout::new::<u8>()).unwrap();
+    let word = arena.alloc(Layout::new::<u64>()).unwrap();
+    assert_eq!(word.as_ptr() as usize % 8, 0);
+    assert_eq!(word.as_ptr() as usize - byte.as_ptr() as usize, 8);
+
+    // 非2的幂的大小不应影响对齐
+    let odd = arena.alloc(Layout::from_size_align(3, 1).unwrap()).unwrap();
+    let next = arena.alloc(Layout::from_size_align(5, 1).unwrap()).unwrap();
+    assert_eq!(next.as_ptr() as usize - odd.as_ptr() as usize, 3);
+
+    let aligned = arena.alloc(Layout::from_size_align(16, 16).unwrap()).unwrap();
+    assert_eq!(aligned.as_ptr() as usize % 16, 0);
+}
+
+#[test_case]
+fn arena_exhaustion() {
+    let mut arena = Arena::new(region());
+    let layout = Layout::from_size_align(100, 4).unwrap();
+
+    let mut count = 0;
+    while arena.alloc(layout).is_some() {
+        count += 1;
+    }
+    assert_eq!(count, REGION_SIZE / 100);
+    assert!(arena.remaining() < 100);
+    assert!(arena.alloc(Layout::from_size_align(arena.remaining(), 1).unwrap()).is_some());
+    assert_eq!(arena.remaining(), 0);
+}
+
+#[test_case]
+fn arena_reset() {
+    let mut arena = Arena::new(region());
+    let layout = Layout::from_size_align(REGION_SIZE, 1).unwrap();
+
+    let first = arena.alloc(layout).unwrap();
+    assert!(arena.alloc(Layout::new::<u8>()).is_none());
+
+    arena.reset();
+    assert_eq!(arena.used(), 0);
+    assert_eq!(arena.alloc(layout), Some(first));
+}
+
+#[test_case]
+fn bump_allocator_alignment() {
+    let allocator = Locked::new(BumpAllocator::new());
+    unsafe { allocator.lock().init(region().as_mut_ptr() as usize, REGION_SIZE) };
+
+    let small = Layout::from_size_align(24, 8).unwrap();
+    unsafe {
+        let a = allocator.alloc(small);
+        let b = allocator.alloc(small);
+        assert_eq!(b as usize - a as usize, 24);
+
+        allocator.dealloc(a, small);
+        allocator.dealloc(b, small);
+        assert_eq!(allocator.alloc(small), a);
+        allocator.dealloc(a, small);
+    }
+}
@@ -0,0 +1,38 @@
+#![no_std]
+#![no_main]
+
+use core::{arch::asm, panic::PanicInfo};
+
+use rust_os::{exit_qemu, interrupts::exceptions, serial_print, serial_println, QemuExitCode};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("exception_divide_error::divide_by_zero...\t");
+
+    rust_os::init();
+
+    // 除数为0
+    unsafe {
+        asm!("div {}", in(reg) 0u64, inout("rax") 1u64 => _, inout("rdx") 0u64 => _);
+    }
+
+    serial_println!("[test did not panic]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    match exceptions::last_fatal_exception() {
+        Some((0, None)) => {
+            serial_println!("[ok]");
+            exit_qemu(QemuExitCode::Success);
+        }
+        other => {
+            serial_println!("[failed]");
+            serial_println!("unexpected exception: {:?}", other);
+            exit_qemu(QemuExitCode::Failed);
+        }
+    }
+    loop {}
+}
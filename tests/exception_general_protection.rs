@@ -0,0 +1,36 @@
+#![no_std]
+#![no_main]
+
+use core::{arch::asm, panic::PanicInfo};
+
+use rust_os::{exit_qemu, interrupts::exceptions, serial_print, serial_println, QemuExitCode};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("exception_general_protection::load_invalid_selector...\t");
+
+    rust_os::init();
+
+    // 选择子超出GDT的范围, 错误码为该选择子
+    unsafe { asm!("mov ds, {:x}", in(reg) 0xfff8u16) };
+
+    serial_println!("[test did not panic]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    match exceptions::last_fatal_exception() {
+        Some((13, Some(0xfff8))) => {
+            serial_println!("[ok]");
+            exit_qemu(QemuExitCode::Success);
+        }
+        other => {
+            serial_println!("[failed]");
+            serial_println!("unexpected exception: {:?}", other);
+            exit_qemu(QemuExitCode::Failed);
+        }
+    }
+    loop {}
+}
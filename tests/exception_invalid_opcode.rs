@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+use core::{arch::asm, panic::PanicInfo};
+
+use rust_os::{exit_qemu, interrupts::exceptions, serial_print, serial_println, QemuExitCode};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("exception_invalid_opcode::ud2...\t");
+
+    rust_os::init();
+
+    // 异常报告应包含执行ud2时的寄存器
+    unsafe {
+        asm!(
+            "ud2",
+            in("rax") 0x1111u64,
+            in("rcx") 0x2222u64,
+            in("r12") 0x3333u64,
+        )
+    };
+
+    serial_println!("[test did not panic]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+fn registers_match() -> bool {
+    match exceptions::last_fatal_registers() {
+        Some(registers) => {
+            registers.rax == 0x1111 && registers.rcx == 0x2222 && registers.r12 == 0x3333
+        }
+        None => false,
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    match exceptions::last_fatal_exception() {
+        Some((6, None)) if registers_match() => {
+            serial_println!("[ok]");
+            exit_qemu(QemuExitCode::Success);
+        }
+        other => {
+            serial_println!("[failed]");
+            serial_println!("unexpected exception: {:?}", other);
+            exit_qemu(QemuExitCode::Failed);
+        }
+    }
+    loop {}
+}
@@ -0,0 +1,36 @@
+#![no_std]
+#![no_main]
+
+use core::{arch::asm, panic::PanicInfo};
+
+use rust_os::{exit_qemu, interrupts::exceptions, serial_print, serial_println, QemuExitCode};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("exception_segment_not_present::missing_idt_entry...\t");
+
+    rust_os::init();
+
+    // 0x90号中断没有处理函数, 错误码为IDT中的0x90项: (0x90 << 3) | 2
+    unsafe { asm!("int 0x90") };
+
+    serial_println!("[test did not panic]");
+    exit_qemu(QemuExitCode::Failed);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    match exceptions::last_fatal_exception() {
+        Some((11, Some(0x482))) => {
+            serial_println!("[ok]");
+            exit_qemu(QemuExitCode::Success);
+        }
+        other => {
+            serial_println!("[failed]");
+            serial_println!("unexpected exception: {:?}", other);
+            exit_qemu(QemuExitCode::Failed);
+        }
+    }
+    loop {}
+}
@@ -0,0 +1,105 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rust_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::{
+    structures::paging::{FrameAllocator, FrameDeallocator},
+    VirtAddr,
+};
+
+use rust_os::memory::frame_allocator::FreeListFrameAllocator;
+
+entry_point!(main);
+
+lazy_static! {
+    static ref FRAME_ALLOCATOR: Mutex<Option<FreeListFrameAllocator>> = Mutex::new(None);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    rust_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let frame_allocator =
+        unsafe { FreeListFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    *FRAME_ALLOCATOR.lock() = Some(frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+#[test_case]
+fn allocate_distinct_frames() {
+    let mut guard = FRAME_ALLOCATOR.lock();
+    let allocator = guard.as_mut().unwrap();
+
+    let a = allocator.allocate_frame().unwrap();
+    let b = allocator.allocate_frame().unwrap();
+    assert_ne!(a, b);
+
+    unsafe {
+        allocator.deallocate_frame(b);
+        allocator.deallocate_frame(a);
+    }
+}
+
+#[test_case]
+fn reuse_freed_frame() {
+    let mut guard = FRAME_ALLOCATOR.lock();
+    let allocator = guard.as_mut().unwrap();
+
+    let frame = allocator.allocate_frame().unwrap();
+    unsafe { allocator.deallocate_frame(frame) };
+    assert_eq!(allocator.allocate_frame(), Some(frame));
+    unsafe { allocator.deallocate_frame(frame) };
+}
+
+#[test_case]
+fn stats_track_usage() {
+    let mut guard = FRAME_ALLOCATOR.lock();
+    let allocator = guard.as_mut().unwrap();
+
+    let before = allocator.stats();
+    assert_eq!(before.used_frames + before.free_frames, before.total_frames);
+
+    let frame = allocator.allocate_frame().unwrap();
+    assert_eq!(allocator.stats().used_frames, before.used_frames + 1);
+    assert_eq!(allocator.stats().free_frames, before.free_frames - 1);
+
+    unsafe { allocator.deallocate_frame(frame) };
+    assert_eq!(allocator.stats(), before);
+}
+
+#[test_case]
+fn double_free_is_rejected() {
+    let mut guard = FRAME_ALLOCATOR.lock();
+    let allocator = guard.as_mut().unwrap();
+
+    let before = allocator.stats();
+    let frame = allocator.allocate_frame().unwrap();
+    unsafe {
+        allocator.deallocate_frame(frame);
+        allocator.deallocate_frame(frame);
+    }
+    assert_eq!(allocator.stats(), before);
+    assert_eq!(allocator.ref_count(frame), 0);
+
+    // 重复释放的帧只能被再分配一次
+    let a = allocator.allocate_frame().unwrap();
+    let b = allocator.allocate_frame().unwrap();
+    assert_ne!(a, b);
+    unsafe {
+        allocator.deallocate_frame(b);
+        allocator.deallocate_frame(a);
+    }
+}
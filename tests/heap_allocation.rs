@@ -1,32 +1,50 @@
 #![no_std]
 #![no_main]
 #![feature(custom_test_frameworks)]
-#![test_runner(rust_os::test_runner)]
+#![test_runner(test_runner)]
 #![reexport_test_harness_main = "test_main"]
 
+// 通过 cargo test-alloc-bump, cargo test-alloc-linked-list 和 cargo test-alloc-fixed-block
+// (定义在.cargo/config.toml中) 对每种全局分配器运行该测试
+
 extern crate alloc;
 
 use core::panic::PanicInfo;
 
 use bootloader::{entry_point, BootInfo};
+use rust_os::{allocator, exit_qemu, serial_println, QemuExitCode, Testable};
 
 entry_point!(main);
 
+// 每个测试用例结束后检查是否有未释放的堆内存
+fn test_runner(tests: &[&dyn Testable]) {
+    serial_println!("Running {} tests", tests.len());
+    for test in tests {
+        test.run();
+
+        let stats = allocator::heap_stats();
+        assert_eq!(stats.allocations, 0, "leaked allocations: {:?}", stats);
+        assert_eq!(stats.bytes_in_use, 0, "leaked bytes: {:?}", stats);
+    }
+    exit_qemu(QemuExitCode::Success);
+}
+
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     rust_os::test_panic_handler(info)
 }
 
 fn main(boot_info: &'static BootInfo) -> ! {
-    use rust_os::allocator;
-    use rust_os::memory::{self, BootInfoFrameAllocator};
+    use rust_os::memory::{self, frame_allocator::FreeListFrameAllocator};
     use x86_64::VirtAddr;
 
     rust_os::init();
     let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
     let mut mapper = unsafe { memory::init(phys_mem_offset) };
-    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    let mut frame_allocator =
+        unsafe { FreeListFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
     allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
 
     test_main();
     loop {}
@@ -64,6 +82,8 @@ fn many_boxes() {
     }
 }
 
+// bump分配器只有在所有分配都释放后才能复用内存
+#[cfg(not(feature = "alloc-bump"))]
 #[test_case]
 fn many_boxes_long_lived() {
     let long_lived = Box::new(1);
@@ -73,3 +93,89 @@ fn many_boxes_long_lived() {
     }
     assert_eq!(*long_lived, 1);
 }
+
+// 只有FixedSizeBlock分配器支持扩展堆
+#[cfg(not(any(feature = "alloc-bump", feature = "alloc-linked-list")))]
+#[test_case]
+fn heap_grows_beyond_initial_size() {
+    let size = 4 * HEAP_SIZE;
+    let mut vec = Vec::with_capacity(size);
+    for i in 0..size {
+        vec.push(i as u8);
+    }
+    assert_eq!(vec.len(), size);
+    assert_eq!(vec[size - 1], (size - 1) as u8);
+}
+
+// 扩展堆时2MiB对齐的部分映射为2MiB大页
+#[cfg(not(any(feature = "alloc-bump", feature = "alloc-linked-list")))]
+#[test_case]
+fn heap_grows_with_huge_pages() {
+    use rust_os::memory::dump;
+    use x86_64::{
+        structures::paging::{PageSize, Size2MiB},
+        VirtAddr,
+    };
+
+    let huge_size = Size2MiB::SIZE as usize;
+    let vec: Vec<u8> = Vec::with_capacity(2 * huge_size);
+    let start = vec.as_ptr() as u64;
+
+    let mut huge_pages = 0;
+    dump::for_each_mapping(|range| {
+        let overlaps = range.virt_start.as_u64() < start + 2 * huge_size as u64
+            && range.virt_end() > VirtAddr::new(start);
+        if overlaps && range.page_size == huge_size as u64 {
+            huge_pages += 1;
+        }
+    });
+    assert!(huge_pages > 0);
+}
+
+#[test_case]
+fn stats_track_allocations() {
+    let before = allocator::heap_stats();
+    let value = Box::new([0u64; 8]);
+    let stats = allocator::heap_stats();
+    assert_eq!(stats.allocations, before.allocations + 1);
+    assert_eq!(stats.bytes_in_use, before.bytes_in_use + 64);
+    assert!(stats.peak_bytes_in_use >= stats.bytes_in_use);
+    drop(value);
+    assert_eq!(allocator::heap_stats().bytes_in_use, before.bytes_in_use);
+}
+
+#[cfg(not(any(feature = "alloc-bump", feature = "alloc-linked-list")))]
+#[test_case]
+fn shrink_returns_cached_blocks() {
+    let boxes: Vec<Box<u64>> = (0..100).map(Box::new).collect();
+    drop(boxes);
+
+    let before = allocator::heap_stats();
+    assert!(before.free_blocks.iter().sum::<usize>() > 0);
+
+    let reclaimed = allocator::shrink_heap();
+    let after = allocator::heap_stats();
+    assert!(reclaimed > 0);
+    assert_eq!(after.free_blocks.iter().sum::<usize>(), 0);
+    assert!(after.fallback_used < before.fallback_used);
+}
+
+// 持有KERNEL_MEMORY时堆无法扩展, 只能使用lock_kernel_memory预留的空间
+#[cfg(not(any(feature = "alloc-bump", feature = "alloc-linked-list")))]
+#[test_case]
+fn allocation_under_kernel_memory_uses_reserve() {
+    use rust_os::memory;
+
+    allocator::shrink_heap();
+    let stats = allocator::heap_stats();
+    // 占满当前堆, 之后的分配都需要扩展堆
+    let filler = alloc::vec![0u8; stats.heap_size - stats.fallback_used - 256];
+
+    let kernel_memory = memory::lock_kernel_memory();
+    let reserved = alloc::vec![1u8; allocator::HEAP_RESERVE / 2];
+    drop(kernel_memory);
+
+    assert_eq!(reserved[allocator::HEAP_RESERVE / 2 - 1], 1);
+    drop(reserved);
+    drop(filler);
+}
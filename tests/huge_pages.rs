@@ -0,0 +1,129 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rust_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::{
+    structures::paging::{
+        FrameAllocator, FrameDeallocator, OffsetPageTable, PageSize, PageTable, PageTableFlags,
+        PhysFrame, Size1GiB, Size2MiB, Size4KiB,
+    },
+    PhysAddr, VirtAddr,
+};
+
+use rust_os::memory::{self, buddy::BuddyFrameAllocator, vma::KERNEL_VMA_START};
+
+entry_point!(main);
+
+struct TestMemory {
+    mapper: OffsetPageTable<'static>,
+    frame_allocator: BuddyFrameAllocator,
+    physical_memory_offset: VirtAddr,
+}
+
+lazy_static! {
+    static ref MEMORY: Mutex<Option<TestMemory>> = Mutex::new(None);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    rust_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mapper = unsafe { memory::init(phys_mem_offset) };
+    let frame_allocator =
+        unsafe { BuddyFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    *MEMORY.lock() = Some(TestMemory {
+        mapper,
+        frame_allocator,
+        physical_memory_offset: phys_mem_offset,
+    });
+
+    test_main();
+    loop {}
+}
+
+#[test_case]
+fn translate_physical_memory_mapping() {
+    // 引导程序可能使用大页映射整个物理内存
+    let guard = MEMORY.lock();
+    let offset = guard.as_ref().unwrap().physical_memory_offset;
+    for &phys in &[0x1234u64, 0xb8000, 0x20_1000] {
+        let virt = offset + phys;
+        assert_eq!(memory::translate_addr(virt, offset), Some(PhysAddr::new(phys)));
+    }
+}
+
+#[test_case]
+fn map_2mib_page() {
+    let mut guard = MEMORY.lock();
+    let memory = guard.as_mut().unwrap();
+
+    let frame: PhysFrame<Size2MiB> = memory.frame_allocator.allocate_frame().unwrap();
+    let virt = VirtAddr::new(KERNEL_VMA_START + 0x4000_0000);
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    unsafe {
+        memory::map_physical_range(
+            virt,
+            frame.start_address(),
+            Size2MiB::SIZE,
+            flags,
+            &mut memory.mapper,
+            &mut memory.frame_allocator,
+        )
+        .unwrap();
+    }
+
+    let inner = virt + 0x12_3456u64;
+    assert_eq!(
+        memory::translate_addr(inner, memory.physical_memory_offset),
+        Some(frame.start_address() + 0x12_3456u64)
+    );
+
+    let ptr: *mut u64 = inner.align_down(8u64).as_mut_ptr();
+    unsafe {
+        ptr.write_volatile(42);
+        assert_eq!(ptr.read_volatile(), 42);
+    }
+}
+
+// 在一个未使用的P4项下手工构造1GiB大页, translate_addr只遍历页表, 不要求CPU支持1GiB大页
+#[test_case]
+fn translate_1gib_page_ignores_pat_bit() {
+    let mut guard = MEMORY.lock();
+    let memory = guard.as_mut().unwrap();
+    let offset = memory.physical_memory_offset;
+
+    let table_frame: PhysFrame<Size4KiB> = memory.frame_allocator.allocate_frame().unwrap();
+    let level_3: &mut PageTable =
+        unsafe { &mut *(offset + table_frame.start_address().as_u64()).as_mut_ptr() };
+    level_3.zero();
+    // 第12位在大页项中是PAT位
+    let phys = PhysAddr::new(Size1GiB::SIZE);
+    level_3[1].set_addr(
+        phys + 0x1000u64,
+        PageTableFlags::PRESENT | PageTableFlags::HUGE_PAGE,
+    );
+
+    let level_4 = memory.mapper.level_4_table();
+    let index = (1..256).find(|&index| level_4[index].is_unused()).unwrap();
+    level_4[index].set_frame(table_frame, PageTableFlags::PRESENT);
+
+    let virt = VirtAddr::new(((index as u64) << 39) | Size1GiB::SIZE | 0x1234_5678);
+    let translated = memory::translate_addr(virt, offset);
+
+    level_4[index].set_unused();
+    x86_64::instructions::tlb::flush_all();
+    unsafe { memory.frame_allocator.deallocate_frame(table_frame) };
+
+    assert_eq!(translated, Some(phys + 0x1234_5678u64));
+}
@@ -0,0 +1,124 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rust_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::{
+    arch::x86_64::_rdtsc,
+    panic::PanicInfo,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use bootloader::{entry_point, BootInfo};
+
+use rust_os::interrupts::{
+    irq::{self, IrqError, CASCADE_LINE, MAX_SHARED_HANDLERS},
+    InterruptIndex,
+};
+
+entry_point!(main);
+
+// 等待一次时钟中断的TSC周期数上限, 远大于PIT的默认周期(约55ms)
+const TICK_TIMEOUT_CYCLES: u64 = 10_000_000_000;
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::test_panic_handler(info)
+}
+
+fn main(_boot_info: &'static BootInfo) -> ! {
+    rust_os::init();
+    test_main();
+    loop {}
+}
+
+static SHARED_TIMER_CALLS: AtomicU64 = AtomicU64::new(0);
+
+fn shared_timer_handler(line: u8) {
+    assert_eq!(line, InterruptIndex::Timer.irq());
+    SHARED_TIMER_CALLS.fetch_add(1, Ordering::Relaxed);
+}
+
+fn unused_handler(_line: u8) {}
+
+// 等待若干次时钟中断, 超过TSC期限仍未等到时返回false
+fn wait_ticks(ticks: u64) -> bool {
+    let timer = InterruptIndex::Timer.irq();
+    let target = irq::irq_count(timer) + ticks;
+    let deadline = unsafe { _rdtsc() } + ticks * TICK_TIMEOUT_CYCLES;
+    while irq::irq_count(timer) < target {
+        if unsafe { _rdtsc() } > deadline {
+            return false;
+        }
+        core::hint::spin_loop();
+    }
+    true
+}
+
+#[test_case]
+fn builtin_handlers_are_registered() {
+    assert_eq!(irq::handler_count(InterruptIndex::Timer.irq()), 1);
+    assert_eq!(irq::handler_count(InterruptIndex::Keyboard.irq()), 1);
+}
+
+#[test_case]
+fn timer_interrupts_are_counted() {
+    let before = irq::irq_count(InterruptIndex::Timer.irq());
+    assert!(wait_ticks(2), "timer interrupts stopped");
+    assert!(irq::irq_count(InterruptIndex::Timer.irq()) >= before + 2);
+}
+
+#[test_case]
+fn shared_handler_runs_with_builtin_handler() {
+    let timer = InterruptIndex::Timer.irq();
+    let handle = irq::register_irq(timer, shared_timer_handler).expect("register failed");
+    assert_eq!(handle.line(), timer);
+    assert_eq!(irq::handler_count(timer), 2);
+
+    assert!(wait_ticks(3), "timer interrupts stopped");
+    assert!(SHARED_TIMER_CALLS.load(Ordering::Relaxed) >= 2);
+
+    irq::unregister_irq(handle).expect("unregister failed");
+    assert_eq!(irq::handler_count(timer), 1);
+    // 内置的时钟处理函数仍在, 中断线没有被屏蔽
+    let calls = SHARED_TIMER_CALLS.load(Ordering::Relaxed);
+    assert!(wait_ticks(2), "timer interrupts stopped");
+    assert_eq!(SHARED_TIMER_CALLS.load(Ordering::Relaxed), calls);
+    assert_eq!(irq::unregister_irq(handle), Err(IrqError::NotRegistered));
+}
+
+#[test_case]
+fn line_is_limited_to_max_shared_handlers() {
+    let line = 5;
+    let handles: [_; MAX_SHARED_HANDLERS] =
+        core::array::from_fn(|_| irq::register_irq(line, unused_handler).unwrap());
+    assert_eq!(
+        irq::register_irq(line, unused_handler),
+        Err(IrqError::LineFull(line))
+    );
+
+    for handle in handles {
+        irq::unregister_irq(handle).unwrap();
+    }
+    assert_eq!(irq::handler_count(line), 0);
+}
+
+#[test_case]
+fn invalid_line_is_rejected() {
+    assert_eq!(
+        irq::register_irq(16, unused_handler),
+        Err(IrqError::InvalidLine(16))
+    );
+    assert_eq!(irq::irq_count(16), 0);
+}
+
+// 8259的级联线不能注册处理函数
+#[test_case]
+fn cascade_line_is_rejected() {
+    assert_eq!(
+        irq::register_irq(CASCADE_LINE, unused_handler),
+        Err(IrqError::InvalidLine(CASCADE_LINE))
+    );
+    assert_eq!(irq::handler_count(CASCADE_LINE), 0);
+}
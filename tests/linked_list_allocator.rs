@@ -0,0 +1,115 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rust_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    panic::PanicInfo,
+    ptr,
+};
+
+use rust_os::allocator::{
+    linked_list::{FitStrategy, LinkedListAllocator},
+    Locked,
+};
+
+const HEAP_SIZE: usize = 4096;
+
+#[repr(align(64))]
+struct Heap([u8; HEAP_SIZE]);
+
+static mut HEAP: Heap = Heap([0; HEAP_SIZE]);
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    test_main();
+
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::test_panic_handler(info)
+}
+
+// 在测试用的静态内存上创建一个分配器
+fn new_allocator(strategy: FitStrategy) -> Locked<LinkedListAllocator> {
+    let allocator = Locked::new(LinkedListAllocator::with_strategy(strategy));
+    unsafe {
+        let heap_start = ptr::addr_of_mut!(HEAP) as usize;
+        allocator.lock().init(heap_start, HEAP_SIZE);
+    }
+    allocator
+}
+
+#[test_case]
+fn full_heap_coalesces() {
+    let allocator = new_allocator(FitStrategy::FirstFit);
+    let layout = Layout::from_size_align(64, 8).unwrap();
+
+    // 占满整个堆
+    let mut blocks = [ptr::null_mut(); HEAP_SIZE / 64];
+    let mut count = 0;
+    loop {
+        let block = unsafe { allocator.alloc(layout) };
+        if block.is_null() {
+            break;
+        }
+        blocks[count] = block;
+        count += 1;
+    }
+    assert_eq!(count, HEAP_SIZE / 64);
+
+    // 打乱释放顺序: 先释放偶数块, 再释放奇数块
+    for block in blocks.iter().step_by(2).chain(blocks.iter().skip(1).step_by(2)) {
+        unsafe { allocator.dealloc(*block, layout) };
+    }
+
+    // 超过ListNode对齐要求的分配在前部留下的空隙也要归还
+    let small = Layout::from_size_align(16, 8).unwrap();
+    let aligned = Layout::from_size_align(64, 64).unwrap();
+    unsafe {
+        let a = allocator.alloc(small);
+        let b = allocator.alloc(aligned);
+        assert_eq!(b as usize % 64, 0);
+        allocator.dealloc(a, small);
+        allocator.dealloc(b, aligned);
+    }
+
+    let stats = allocator.stats();
+    assert_eq!(stats.allocations, 0);
+    assert_eq!(stats.free_regions, 1);
+    assert_eq!(stats.largest_free_region, HEAP_SIZE);
+}
+
+#[test_case]
+fn best_fit_prefers_smallest_region() {
+    for strategy in [FitStrategy::FirstFit, FitStrategy::BestFit] {
+        let allocator = new_allocator(strategy);
+        let expect_small = strategy == FitStrategy::BestFit;
+
+        // 构造空闲区域: [256字节] [16字节已用] [64字节] [16字节已用] [剩余空间]
+        let large = Layout::from_size_align(256, 8).unwrap();
+        let small = Layout::from_size_align(64, 8).unwrap();
+        let guard = Layout::from_size_align(16, 8).unwrap();
+        unsafe {
+            let a = allocator.alloc(large);
+            let b = allocator.alloc(guard);
+            let c = allocator.alloc(small);
+            let d = allocator.alloc(guard);
+            allocator.dealloc(a, large);
+            allocator.dealloc(c, small);
+
+            let block = allocator.alloc(small);
+            assert_eq!(block == c, expect_small);
+            assert_eq!(block == a, !expect_small);
+
+            allocator.dealloc(block, small);
+            allocator.dealloc(b, guard);
+            allocator.dealloc(d, guard);
+        }
+        assert_eq!(allocator.stats().free_regions, 1);
+    }
+}
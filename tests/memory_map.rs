@@ -0,0 +1,120 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rust_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::collections::BTreeSet;
+use core::{panic::PanicInfo, ptr};
+
+use bootloader::{
+    bootinfo::{MemoryMap, MemoryRegionType},
+    entry_point, BootInfo,
+};
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::{PhysAddr, VirtAddr};
+
+use rust_os::{
+    allocator,
+    memory::{self, frame_allocator::FreeListFrameAllocator, protection, report, vma},
+};
+
+entry_point!(main);
+
+lazy_static! {
+    static ref MEMORY_MAP: Mutex<Option<&'static MemoryMap>> = Mutex::new(None);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    rust_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator =
+        unsafe { FreeListFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+    *MEMORY_MAP.lock() = Some(&boot_info.memory_map);
+
+    test_main();
+    loop {}
+}
+
+fn memory_map() -> &'static MemoryMap {
+    MEMORY_MAP.lock().unwrap()
+}
+
+#[test_case]
+fn summary_matches_frame_allocator() {
+    let summary = report::summary(memory_map());
+    let frames = {
+        let kernel_memory = memory::KERNEL_MEMORY.lock();
+        kernel_memory.as_ref().unwrap().frame_allocator.stats()
+    };
+    assert_eq!(summary.usable_bytes, frames.total_frames as u64 * 4096);
+    assert!(summary.kernel_bytes > 0);
+    assert!(summary.usable_bytes + summary.kernel_bytes <= summary.total_bytes);
+}
+
+#[test_case]
+fn summary_counts_heap_frames() {
+    let summary = report::summary(memory_map());
+    assert_eq!(
+        summary.heap_frames * 4096,
+        allocator::heap_stats().heap_size
+    );
+    assert!(summary.heap_frames >= allocator::HEAP_SIZE / 4096);
+}
+
+// 由链接器定义, 位于内核ELF文件头的起始处
+extern "C" {
+    static __ehdr_start: u8;
+}
+
+// 内核镜像各段映射到的物理帧都应位于Kernel类型的区域中
+#[test_case]
+fn kernel_regions_cover_kernel_image() {
+    let map = memory_map();
+    let offset = memory::physical_memory_offset().unwrap();
+    let phys_of = |addr: VirtAddr| memory::translate_addr(addr, offset).unwrap();
+    let in_kernel_region = |phys: PhysAddr| {
+        map.iter().any(|region| {
+            region.region_type == MemoryRegionType::Kernel
+                && region.range.start_addr() <= phys.as_u64()
+                && phys.as_u64() < region.range.end_addr()
+        })
+    };
+
+    let header = VirtAddr::from_ptr(unsafe { ptr::addr_of!(__ehdr_start) });
+    assert!(in_kernel_region(phys_of(header)));
+
+    let mut frames = BTreeSet::new();
+    for segment in protection::kernel_segments() {
+        for page in segment.pages() {
+            let addr = page.start_address();
+            let phys = match memory::translate_addr(addr, offset) {
+                Some(phys) => phys,
+                // .bss中中断栈的保护页已取消映射, 不占用物理帧
+                None => {
+                    assert!(vma::guard_page_owner(addr).is_some(), "{:?} not mapped", page);
+                    continue;
+                }
+            };
+            assert!(in_kernel_region(phys), "{:?} outside kernel regions", page);
+            frames.insert(phys.as_u64());
+        }
+    }
+
+    let kernel = report::bytes_of(map, MemoryRegionType::Kernel);
+    assert!(kernel >= frames.len() as u64 * 4096);
+    assert!(kernel < report::summary(map).total_bytes);
+
+    report::print_memory_map(map);
+}
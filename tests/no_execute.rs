@@ -0,0 +1,87 @@
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use core::{
+    mem,
+    panic::PanicInfo,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use bootloader::{entry_point, BootInfo};
+use lazy_static::lazy_static;
+use x86_64::{
+    registers::control::Cr2,
+    structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode},
+    VirtAddr,
+};
+
+use rust_os::{
+    allocator, exit_qemu,
+    memory::{self, frame_allocator::FreeListFrameAllocator},
+    serial_print, serial_println, QemuExitCode,
+};
+
+entry_point!(main);
+
+// 放在堆上的代码的地址
+static HEAP_CODE: AtomicU64 = AtomicU64::new(0);
+
+extern "x86-interrupt" fn test_page_fault_handler(
+    _stack_frame: InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    let expected = PageFaultErrorCode::PROTECTION_VIOLATION | PageFaultErrorCode::INSTRUCTION_FETCH;
+    if error_code.contains(expected) && Cr2::read().as_u64() == HEAP_CODE.load(Ordering::Relaxed) {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]");
+        serial_println!(
+            "unexpected page fault: {:?} at {:?}",
+            error_code,
+            Cr2::read()
+        );
+        exit_qemu(QemuExitCode::Failed);
+    }
+    loop {}
+}
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.page_fault.set_handler_fn(test_page_fault_handler);
+
+        idt
+    };
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("no_execute::execute_from_heap...\t");
+
+    rust_os::gdt::init();
+    TEST_IDT.load();
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator =
+        unsafe { FreeListFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::protection::protect_kernel_sections(&mut mapper).expect("protection failed");
+
+    // 一条ret指令, 堆不可执行, 调用应触发页错误
+    let code = Box::leak(Box::new([0xc3u8]));
+    HEAP_CODE.store(code.as_ptr() as u64, Ordering::Relaxed);
+    let function: extern "C" fn() = unsafe { mem::transmute(code.as_ptr()) };
+    function();
+
+    panic!("Execution continued after executing from the heap");
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::test_panic_handler(info)
+}
@@ -0,0 +1,119 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rust_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use x86_64::VirtAddr;
+
+use rust_os::{
+    allocator::{slab::ObjectCache, Locked},
+    memory::{self, frame_allocator::FreeListFrameAllocator},
+};
+
+entry_point!(main);
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    value: u64,
+    children: [usize; 4],
+}
+
+fn node_constructor(ptr: *mut u8) {
+    unsafe {
+        (ptr as *mut Node).write(Node {
+            value: 42,
+            children: [0; 4],
+        })
+    };
+}
+
+static NODE_CACHE: Locked<ObjectCache> =
+    Locked::new(ObjectCache::of::<Node>("node", Some(node_constructor)));
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    rust_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mapper = unsafe { memory::init(phys_mem_offset) };
+    let frame_allocator =
+        unsafe { FreeListFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+fn used_frames() -> usize {
+    let kernel_memory = memory::KERNEL_MEMORY.lock();
+    kernel_memory.as_ref().unwrap().frame_allocator.stats().used_frames
+}
+
+#[test_case]
+fn objects_are_constructed() {
+    let mut cache = NODE_CACHE.lock();
+    let object = cache.alloc().unwrap();
+    let node = unsafe { &*(object.as_ptr() as *const Node) };
+    assert_eq!(node.value, 42);
+    assert_eq!(node.children, [0; 4]);
+    unsafe { cache.free(object) };
+}
+
+#[test_case]
+fn slabs_move_between_lists() {
+    let mut cache = NODE_CACHE.lock();
+    let per_slab = cache.stats().objects_per_slab;
+
+    // 占满一个slab, 再多分配一个对象
+    let mut objects = [None; 128];
+    for object in objects.iter_mut().take(per_slab + 1) {
+        *object = cache.alloc();
+    }
+    let stats = cache.stats();
+    assert_eq!(stats.objects_in_use, per_slab + 1);
+    assert!(stats.full_slabs >= 1);
+    assert!(stats.partial_slabs >= 1);
+
+    for object in objects.iter().flatten() {
+        unsafe { cache.free(*object) };
+    }
+    let stats = cache.stats();
+    assert_eq!(stats.objects_in_use, 0);
+    assert_eq!(stats.full_slabs, 0);
+    assert_eq!(stats.partial_slabs, 0);
+    assert!(stats.empty_slabs >= 2);
+}
+
+#[test_case]
+fn reclaim_returns_frames() {
+    let mut cache = NODE_CACHE.lock();
+    cache.reclaim();
+    let before = used_frames();
+
+    let object = cache.alloc().unwrap();
+    assert_eq!(used_frames(), before + 1);
+    unsafe { cache.free(object) };
+
+    assert_eq!(cache.reclaim(), 1);
+    assert_eq!(used_frames(), before);
+    assert_eq!(cache.stats().empty_slabs, 0);
+}
+
+#[test_case]
+fn drop_returns_empty_slabs() {
+    let before = used_frames();
+    {
+        let mut cache = ObjectCache::of::<Node>("local", Some(node_constructor));
+        let object = cache.alloc().unwrap();
+        assert_eq!(used_frames(), before + 1);
+        unsafe { cache.free(object) };
+    }
+    assert_eq!(used_frames(), before);
+}
@@ -0,0 +1,308 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rust_os::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use x86_64::{
+    structures::paging::{
+        mapper::TranslateResult, Page, PageTableFlags, PhysFrame, Translate,
+    },
+    PhysAddr, VirtAddr,
+};
+
+use rust_os::{
+    allocator,
+    memory::{self, dump, frame_allocator::FreeListFrameAllocator, vma},
+};
+
+entry_point!(main);
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::test_panic_handler(info)
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    rust_os::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator =
+        unsafe { FreeListFrameAllocator::init(&boot_info.memory_map, phys_mem_offset) };
+    allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialization failed");
+    memory::init_kernel_memory(mapper, frame_allocator);
+
+    test_main();
+    loop {}
+}
+
+fn is_mapped(addr: VirtAddr) -> bool {
+    let kernel_memory = memory::KERNEL_MEMORY.lock();
+    kernel_memory
+        .as_ref()
+        .unwrap()
+        .mapper
+        .translate_addr(addr)
+        .is_some()
+}
+
+const FLAGS: PageTableFlags = PageTableFlags::PRESENT.union(PageTableFlags::WRITABLE);
+
+#[test_case]
+fn regions_do_not_overlap() {
+    let a = vma::allocate_region(3 * 4096, FLAGS, "a").unwrap();
+    let b = vma::allocate_region(4096, FLAGS, "b").unwrap();
+    assert!(a.end() <= b.start || b.end() <= a.start);
+
+    let vmm = vma::KERNEL_VMM.lock();
+    assert_eq!(vmm.find(a.start + 5000u64).map(|r| r.name), Some("a"));
+    assert_eq!(vmm.find(b.start).map(|r| r.name), Some("b"));
+    drop(vmm);
+
+    vma::free_region(a.start).unwrap();
+    vma::free_region(b.start).unwrap();
+}
+
+#[test_case]
+fn region_is_mapped_and_writable() {
+    let region = vma::allocate_region(2 * 4096, FLAGS, "rw").unwrap();
+    for page in region.pages() {
+        let ptr: *mut u64 = page.start_address().as_mut_ptr();
+        unsafe {
+            ptr.write_volatile(0xdead_beef);
+            assert_eq!(ptr.read_volatile(), 0xdead_beef);
+        }
+    }
+    vma::free_region(region.start).unwrap();
+}
+
+#[test_case]
+fn free_unmaps_and_reuses_address() {
+    let region = vma::allocate_region(4096, FLAGS, "tmp").unwrap();
+    let page: Page = Page::containing_address(region.start);
+    assert!(is_mapped(page.start_address()));
+
+    vma::free_region(region.start).unwrap();
+    assert!(!is_mapped(page.start_address()));
+    assert!(vma::KERNEL_VMM.lock().find(region.start).is_none());
+
+    let again = vma::allocate_region(4096, FLAGS, "tmp").unwrap();
+    assert_eq!(again.start, region.start);
+    vma::free_region(again.start).unwrap();
+}
+
+#[test_case]
+fn dump_reports_region() {
+    let region = vma::allocate_region(2 * 4096, FLAGS, "dump").unwrap();
+
+    let mut found = None;
+    dump::for_each_mapping(|range| {
+        if range.contains(region.start) {
+            found = Some(*range);
+        }
+    });
+    let range = found.expect("region not found in page tables");
+    assert_eq!(range.page_size, 4096);
+    assert!(range.flags.contains(PageTableFlags::WRITABLE));
+
+    dump::dump_page_tables();
+    vma::free_region(region.start).unwrap();
+}
+
+#[test_case]
+fn stack_has_guard_page() {
+    let stack = vma::allocate_stack(4 * 4096, "test stack").unwrap();
+    assert_eq!(stack.size, 5 * 4096);
+    assert!(!is_mapped(stack.start));
+    assert!(is_mapped(stack.start + 4096u64));
+    assert!(is_mapped(stack.end() - 8u64));
+
+    assert_eq!(vma::guard_page_owner(stack.start + 100u64), Some("test stack"));
+    assert_eq!(vma::guard_page_owner(stack.start + 4096u64), None);
+
+    vma::free_region(stack.start).unwrap();
+    assert_eq!(vma::guard_page_owner(stack.start), None);
+}
+
+fn used_frames() -> usize {
+    let kernel_memory = memory::KERNEL_MEMORY.lock();
+    kernel_memory.as_ref().unwrap().frame_allocator.stats().used_frames
+}
+
+#[test_case]
+fn lazy_region_maps_on_touch() {
+    let region = vma::reserve_lazy_region(512 * 4096, FLAGS, "lazy").unwrap();
+    assert!(!is_mapped(region.start));
+
+    // 区域最多跨越两个P1页表, 先访问首尾两页, 使后续访问不再需要分配页表
+    let first: *mut u64 = region.start.as_mut_ptr();
+    let last: *mut u64 = (region.end() - 8u64).as_mut_ptr();
+    unsafe {
+        assert_eq!(first.read_volatile(), 0);
+        last.write_volatile(1);
+    }
+
+    let before = used_frames();
+    for index in [1u64, 100, 300] {
+        let ptr: *mut u64 = (region.start + index * 4096).as_mut_ptr();
+        unsafe {
+            assert_eq!(ptr.read_volatile(), 0);
+            ptr.write_volatile(index);
+            assert_eq!(ptr.read_volatile(), index);
+        }
+    }
+    assert_eq!(used_frames() - before, 3);
+    assert!(!is_mapped(region.start + 2 * 4096u64));
+
+    vma::free_region(region.start).unwrap();
+    assert!(!is_mapped(region.start + 4096u64));
+}
+
+fn frame_of(addr: VirtAddr) -> PhysFrame {
+    let kernel_memory = memory::KERNEL_MEMORY.lock();
+    let phys = kernel_memory.as_ref().unwrap().mapper.translate_addr(addr).unwrap();
+    PhysFrame::containing_address(phys)
+}
+
+fn ref_count(frame: PhysFrame) -> usize {
+    let kernel_memory = memory::KERNEL_MEMORY.lock();
+    kernel_memory.as_ref().unwrap().frame_allocator.ref_count(frame)
+}
+
+#[test_case]
+fn shared_region_copies_on_write() {
+    let region = vma::allocate_region(2 * 4096, FLAGS, "cow source").unwrap();
+    let source: *mut u64 = region.start.as_mut_ptr();
+    unsafe { source.write_volatile(42) };
+
+    let copy = vma::share_region(region.start, "cow copy").unwrap();
+    let shared = frame_of(region.start);
+    assert_eq!(frame_of(copy.start), shared);
+    assert_eq!(ref_count(shared), 2);
+
+    // 写入副本后两者指向不同的帧, 原区域的数据不变
+    let target: *mut u64 = copy.start.as_mut_ptr();
+    unsafe {
+        assert_eq!(target.read_volatile(), 42);
+        target.write_volatile(7);
+        assert_eq!(source.read_volatile(), 42);
+        assert_eq!(target.read_volatile(), 7);
+    }
+    assert_ne!(frame_of(copy.start), shared);
+    assert_eq!(ref_count(shared), 1);
+
+    // 最后一个引用者写入时直接恢复写权限, 不再复制
+    let before = used_frames();
+    unsafe { source.write_volatile(43) };
+    assert_eq!(frame_of(region.start), shared);
+    assert_eq!(used_frames(), before);
+
+    vma::free_region(copy.start).unwrap();
+    vma::free_region(region.start).unwrap();
+}
+
+#[test_case]
+fn freeing_shared_region_keeps_frames() {
+    let region = vma::allocate_region(4096, FLAGS, "shared").unwrap();
+    unsafe { region.start.as_mut_ptr::<u64>().write_volatile(42) };
+    let copy = vma::share_region(region.start, "shared copy").unwrap();
+    let frame = frame_of(region.start);
+
+    let before = used_frames();
+    vma::free_region(region.start).unwrap();
+    assert_eq!(used_frames(), before);
+    assert_eq!(ref_count(frame), 1);
+    assert_eq!(unsafe { copy.start.as_ptr::<u64>().read_volatile() }, 42);
+
+    vma::free_region(copy.start).unwrap();
+    assert_eq!(used_frames(), before - 1);
+}
+
+// 持有KERNEL_MEMORY时堆无法扩展, 共享页面时更新引用计数不能分配堆内存
+#[cfg(not(any(feature = "alloc-bump", feature = "alloc-linked-list")))]
+#[test_case]
+fn sharing_pages_at_heap_limit() {
+    use rust_os::memory::cow;
+
+    let region = vma::allocate_region(8 * 4096, FLAGS, "cow at heap limit").unwrap();
+    let copy = vma::reserve_lazy_region(8 * 4096, FLAGS, "cow at heap limit copy").unwrap();
+
+    allocator::shrink_heap();
+    let stats = allocator::heap_stats();
+    // 占满当前堆, 之后的分配都需要扩展堆
+    let filler = alloc::vec![0u8; stats.heap_size - stats.fallback_used - 256];
+    let before = allocator::heap_stats();
+
+    let mut kernel_memory = memory::KERNEL_MEMORY.lock();
+    let memory = kernel_memory.as_mut().unwrap();
+    for (src, dst) in region.pages().zip(copy.pages()) {
+        cow::share_page(src, dst, &mut memory.mapper, &mut memory.frame_allocator).unwrap();
+    }
+    drop(kernel_memory);
+
+    let after = allocator::heap_stats();
+    assert_eq!(after.heap_size, before.heap_size);
+    assert_eq!(after.allocations, before.allocations);
+    drop(filler);
+
+    let shared = frame_of(region.start);
+    assert_eq!(frame_of(copy.start), shared);
+    assert_eq!(ref_count(shared), 2);
+
+    vma::free_region(copy.start).unwrap();
+    assert_eq!(ref_count(shared), 1);
+    vma::free_region(region.start).unwrap();
+}
+
+#[test_case]
+fn mmio_region_maps_device_memory() {
+    let vga = PhysAddr::new(0xb8000);
+    let mut mmio = memory::map_mmio(vga + 0x10u64, 16).unwrap();
+    let start = mmio.virt_addr();
+    assert_eq!(start.as_u64() % 4096, 0x10);
+
+    let flags = {
+        let kernel_memory = memory::KERNEL_MEMORY.lock();
+        match kernel_memory.as_ref().unwrap().mapper.translate(start) {
+            TranslateResult::Mapped { flags, .. } => flags,
+            _ => panic!("mmio region not mapped"),
+        }
+    };
+    assert!(flags.contains(memory::mmio::MMIO_FLAGS));
+
+    // 与物理内存映射访问的是同一块显存
+    let physical_memory_offset = memory::physical_memory_offset().unwrap();
+    let alias: *const u16 = (physical_memory_offset + vga.as_u64() + 0x10u64).as_ptr();
+    mmio.write::<u16>(2, 0x0f41);
+    assert_eq!(unsafe { alias.add(1).read_volatile() }, 0x0f41);
+    assert_eq!(mmio.register::<u16>(2).read(), 0x0f41);
+
+    drop(mmio);
+    assert!(!is_mapped(start));
+    assert!(vma::KERNEL_VMM.lock().find(start).is_none());
+}
+
+// 设备内存不由帧分配器管理, 不能通过写时复制共享
+#[test_case]
+fn device_memory_is_not_shareable() {
+    use rust_os::memory::cow::{self, CowError};
+
+    let mmio = memory::map_mmio(PhysAddr::new(0xb8000), 4096).unwrap();
+    let copy = vma::reserve_lazy_region(4096, FLAGS, "mmio copy").unwrap();
+    let src = Page::containing_address(mmio.virt_addr());
+    let dst = Page::containing_address(copy.start);
+
+    let mut kernel_memory = memory::KERNEL_MEMORY.lock();
+    let memory = kernel_memory.as_mut().unwrap();
+    let result = cow::share_page(src, dst, &mut memory.mapper, &mut memory.frame_allocator);
+    drop(kernel_memory);
+    assert!(matches!(result, Err(CowError::NotShareable)));
+    assert!(!is_mapped(copy.start));
+
+    vma::free_region(copy.start).unwrap();
+}
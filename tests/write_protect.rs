@@ -0,0 +1,71 @@
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+use core::panic::PanicInfo;
+
+use bootloader::{entry_point, BootInfo};
+use lazy_static::lazy_static;
+use x86_64::{
+    registers::control::Cr2,
+    structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode},
+    VirtAddr,
+};
+
+use rust_os::{exit_qemu, memory, serial_print, serial_println, QemuExitCode};
+
+entry_point!(main);
+
+extern "x86-interrupt" fn test_page_fault_handler(
+    _stack_frame: InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    let expected = PageFaultErrorCode::PROTECTION_VIOLATION | PageFaultErrorCode::CAUSED_BY_WRITE;
+    if error_code.contains(expected) && Cr2::read() == VirtAddr::new(code_addr()) {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    } else {
+        serial_println!("[failed]");
+        serial_println!(
+            "unexpected page fault: {:?} at {:?}",
+            error_code,
+            Cr2::read()
+        );
+        exit_qemu(QemuExitCode::Failed);
+    }
+    loop {}
+}
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.page_fault.set_handler_fn(test_page_fault_handler);
+
+        idt
+    };
+}
+
+fn code_addr() -> u64 {
+    code_addr as fn() -> u64 as usize as u64
+}
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("write_protect::write_to_code...\t");
+
+    rust_os::gdt::init();
+    TEST_IDT.load();
+
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    memory::protection::protect_kernel_sections(&mut mapper).expect("protection failed");
+
+    // .text只读, 写入应触发页错误
+    unsafe { (code_addr() as *mut u8).write_volatile(0xc3) };
+
+    panic!("Execution continued after writing to code");
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::test_panic_handler(info)
+}
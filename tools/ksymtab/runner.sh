@@ -0,0 +1,15 @@
+#!/bin/sh
+# cargo run/test的runner, 由.cargo/config.toml按路径调用, 不需要事先安装ksymtab
+# ksymtab只有一个源文件且没有依赖, 直接用rustc构建到构建目录中, 源文件更新后重新构建
+# 不经过cargo构建, 因此不受内核.cargo/config.toml中target, build-std和链接参数的影响
+set -e
+
+root=$(cd "$(dirname "$0")/../.." && pwd)
+source="$root/tools/ksymtab/src/main.rs"
+binary="${CARGO_TARGET_DIR:-$root/target}/ksymtab/ksymtab"
+
+if [ ! -x "$binary" ] || [ "$source" -nt "$binary" ]; then
+    mkdir -p "$(dirname "$binary")"
+    rustc --edition 2021 -O --crate-name ksymtab -o "$binary" "$source"
+fi
+exec "$binary" "$@"
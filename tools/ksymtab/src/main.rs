@@ -0,0 +1,274 @@
+// cargo run/test的runner: 将内核ELF中的函数符号写入内核预留的.ksymtab段, 再交给bootimage runner启动
+// 由tools/ksymtab/runner.sh在构建目录中构建并调用, 也可以手动安装: cargo install --path tools/ksymtab
+// 用法与bootimage runner相同: ksymtab <kernel-elf> [args...]
+//
+// .ksymtab的格式, 与src/backtrace/symbols.rs一致:
+//   magic: [u8; 8] = "KSYMTAB\0"
+//   count: u32, strings_offset: u32 (相对于段的起始)
+//   count个按地址排序的符号: addr: u64, size: u32, name_offset: u32, name_len: u32, 填充: u32
+//   符号名, 不以0结尾
+//
+// .ksymtab的大小在编译时确定, 这里把容纳所有符号所需的大小写入构建目录下的ksymtab-size,
+// build.rs在下一次构建时据此分配
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
+
+const MAGIC: &[u8; 8] = b"KSYMTAB\0";
+const HEADER_SIZE: usize = 16;
+const ENTRY_SIZE: usize = 24;
+const PAGE_SIZE: usize = 4096;
+const SIZE_FILE: &str = "ksymtab-size";
+
+const SHT_SYMTAB: u32 = 2;
+const STT_FUNC: u8 = 2;
+
+struct Section {
+    name: u32,
+    kind: u32,
+    offset: usize,
+    size: usize,
+    link: u32,
+}
+
+struct Symbol {
+    addr: u64,
+    size: u64,
+    name: String,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn c_str(data: &[u8], offset: usize) -> &str {
+    let end = data[offset..].iter().position(|&b| b == 0).unwrap_or(0);
+    std::str::from_utf8(&data[offset..offset + end]).unwrap_or("")
+}
+
+fn sections(elf: &[u8]) -> Vec<Section> {
+    let shoff = u64_at(elf, 0x28) as usize;
+    let shentsize = u16_at(elf, 0x3a) as usize;
+    let shnum = u16_at(elf, 0x3c) as usize;
+
+    (0..shnum)
+        .map(|index| {
+            let header = shoff + index * shentsize;
+            Section {
+                name: u32_at(elf, header),
+                kind: u32_at(elf, header + 4),
+                offset: u64_at(elf, header + 24) as usize,
+                size: u64_at(elf, header + 32) as usize,
+                link: u32_at(elf, header + 40),
+            }
+        })
+        .collect()
+}
+
+// 解码旧式的Rust符号名, 例如_ZN7rust_os4main17h0123456789abcdefE -> rust_os::main
+fn demangle(name: &str) -> String {
+    let mut rest = match name.strip_prefix("_ZN") {
+        Some(rest) => rest,
+        None => return name.to_string(),
+    };
+
+    let mut parts = Vec::new();
+    while !rest.starts_with('E') {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let len: usize = match rest[..digits].parse() {
+            Ok(len) if digits > 0 && rest.len() >= digits + len => len,
+            _ => return name.to_string(),
+        };
+        // 以转义字符开头的部分前面会多一个下划线
+        let part = &rest[digits..digits + len];
+        parts.push(
+            part.strip_prefix('_')
+                .filter(|p| p.starts_with('$'))
+                .unwrap_or(part),
+        );
+        rest = &rest[digits + len..];
+    }
+
+    // 最后一段是符号的哈希
+    if let Some(last) = parts.last() {
+        if last.len() == 17 && last.starts_with('h') {
+            parts.pop();
+        }
+    }
+
+    let escapes = [
+        ("$LT$", "<"),
+        ("$GT$", ">"),
+        ("$RF$", "&"),
+        ("$BP$", "*"),
+        ("$C$", ","),
+        ("$SP$", "@"),
+        ("$u20$", " "),
+        ("$u27$", "'"),
+        ("$u5b$", "["),
+        ("$u5d$", "]"),
+        ("$u7b$", "{"),
+        ("$u7d$", "}"),
+        ("$u7e$", "~"),
+        ("..", "::"),
+    ];
+    let mut demangled = parts.join("::");
+    for (from, to) in escapes {
+        demangled = demangled.replace(from, to);
+    }
+    demangled
+}
+
+fn function_symbols(elf: &[u8], sections: &[Section]) -> Vec<Symbol> {
+    let symtab = match sections.iter().find(|s| s.kind == SHT_SYMTAB) {
+        Some(symtab) => symtab,
+        None => return Vec::new(),
+    };
+    let strtab = &sections[symtab.link as usize];
+
+    let mut symbols: Vec<Symbol> = (0..symtab.size / ENTRY_SIZE)
+        .map(|index| symtab.offset + index * ENTRY_SIZE)
+        .filter(|&entry| elf[entry + 4] & 0xf == STT_FUNC)
+        .map(|entry| Symbol {
+            addr: u64_at(elf, entry + 8),
+            size: u64_at(elf, entry + 16),
+            name: demangle(c_str(elf, strtab.offset + u32_at(elf, entry) as usize)),
+        })
+        .filter(|symbol| symbol.addr != 0)
+        .collect();
+
+    symbols.sort_by_key(|symbol| symbol.addr);
+    symbols.dedup_by_key(|symbol| symbol.addr);
+    symbols
+}
+
+// 与build.rs约定的ksymtab-size位置: 内核所在的构建目录, 测试程序位于其下的deps中
+fn size_file(elf: &Path) -> Option<PathBuf> {
+    let dir = elf.parent()?;
+    let dir = match dir.file_name() {
+        Some(name) if name == "deps" => dir.parent()?,
+        _ => dir,
+    };
+    Some(dir.join(SIZE_FILE))
+}
+
+// 记录容纳所有符号所需的.ksymtab大小, 同一构建目录下的内核和各测试程序取最大值
+fn record_size(elf: &Path, needed: usize) {
+    let path = match size_file(elf) {
+        Some(path) => path,
+        None => return,
+    };
+    let recorded = fs::read_to_string(&path)
+        .ok()
+        .and_then(|size| size.trim().parse().ok())
+        .unwrap_or(0);
+    let needed = needed.div_ceil(PAGE_SIZE) * PAGE_SIZE;
+    if needed > recorded {
+        if let Err(err) = fs::write(&path, needed.to_string()) {
+            eprintln!("ksymtab: failed to write {}: {}", path.display(), err);
+        }
+    }
+}
+
+// 将符号表写入path处的内核ELF
+fn patch(path: &str) {
+    let mut elf = fs::read(path).unwrap_or_else(|err| {
+        eprintln!("ksymtab: failed to read {}: {}", path, err);
+        process::exit(1);
+    });
+
+    let sections = sections(&elf);
+    let shstrtab = &sections[u16_at(&elf, 0x3e) as usize];
+    let table = sections
+        .iter()
+        .find(|s| c_str(&elf, shstrtab.offset + s.name as usize) == ".ksymtab");
+    let (offset, capacity) = match table {
+        Some(table) if elf[table.offset..].starts_with(MAGIC) => (table.offset, table.size),
+        // 没有链接栈回溯模块的内核不需要符号表
+        _ => return,
+    };
+
+    let symbols = function_symbols(&elf, &sections);
+    let needed = HEADER_SIZE
+        + symbols
+            .iter()
+            .map(|symbol| ENTRY_SIZE + symbol.name.len())
+            .sum::<usize>();
+    record_size(Path::new(path), needed);
+
+    // 放不下时只保留地址较低的符号
+    let (mut count, mut strings_size) = (0, 0);
+    for symbol in &symbols {
+        if HEADER_SIZE + (count + 1) * ENTRY_SIZE + strings_size + symbol.name.len() > capacity {
+            break;
+        }
+        count += 1;
+        strings_size += symbol.name.len();
+    }
+    if count < symbols.len() {
+        eprintln!(
+            "ksymtab: .ksymtab holds {} of {} symbols, the next build makes room for all of them",
+            count,
+            symbols.len()
+        );
+    }
+
+    let strings_offset = HEADER_SIZE + count * ENTRY_SIZE;
+    let mut out = vec![0u8; capacity];
+    out[..8].copy_from_slice(MAGIC);
+    out[8..12].copy_from_slice(&(count as u32).to_le_bytes());
+    out[12..16].copy_from_slice(&(strings_offset as u32).to_le_bytes());
+
+    let mut name_offset = 0;
+    for (index, symbol) in symbols[..count].iter().enumerate() {
+        let entry = HEADER_SIZE + index * ENTRY_SIZE;
+        let size = symbol.size.min(u64::from(u32::MAX)) as u32;
+        out[entry..entry + 8].copy_from_slice(&symbol.addr.to_le_bytes());
+        out[entry + 8..entry + 12].copy_from_slice(&size.to_le_bytes());
+        out[entry + 12..entry + 16].copy_from_slice(&(name_offset as u32).to_le_bytes());
+        out[entry + 16..entry + 20].copy_from_slice(&(symbol.name.len() as u32).to_le_bytes());
+
+        let start = strings_offset + name_offset;
+        out[start..start + symbol.name.len()].copy_from_slice(symbol.name.as_bytes());
+        name_offset += symbol.name.len();
+    }
+
+    elf[offset..offset + capacity].copy_from_slice(&out);
+    fs::write(path, elf).unwrap_or_else(|err| {
+        eprintln!("ksymtab: failed to write {}: {}", path, err);
+        process::exit(1);
+    });
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let path = match args.first() {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: ksymtab <kernel-elf> [args...]");
+            process::exit(2);
+        }
+    };
+    patch(path);
+
+    let status = Command::new("bootimage")
+        .arg("runner")
+        .args(&args)
+        .status()
+        .unwrap_or_else(|err| {
+            eprintln!("ksymtab: failed to run bootimage: {}", err);
+            process::exit(1);
+        });
+    process::exit(status.code().unwrap_or(1));
+}